@@ -0,0 +1,69 @@
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use octobuild::io::compression::Compression;
+
+// Object files for benchmark: OCTOBUILD_BENCH_FILES (path list) or benchmark executable itself.
+fn sample_files() -> Vec<PathBuf> {
+    match env::var_os("OCTOBUILD_BENCH_FILES") {
+        Some(paths) => env::split_paths(&paths).collect(),
+        None => vec![env::current_exe().unwrap()],
+    }
+}
+
+fn cache_compression_benchmark(c: &mut Criterion) {
+    let codecs = [
+        Compression::None,
+        Compression::Lz4(1),
+        Compression::Lz4(9),
+        Compression::Zstd(1),
+        Compression::Zstd(3),
+        Compression::Zstd(9),
+        Compression::Zstd(19),
+    ];
+    let mut group = c.benchmark_group("cache_compression");
+    for path in sample_files() {
+        let source = fs::read(&path).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        for codec in codecs.iter() {
+            let id = format!("{}-{}/{}", codec.name(), codec.level().unwrap_or(0), name);
+            let mut packed = Vec::new();
+            codec
+                .compress(&mut Cursor::new(&source), &mut packed)
+                .unwrap();
+            println!(
+                "{}: {} -> {} bytes (ratio {:.2})",
+                id,
+                source.len(),
+                packed.len(),
+                source.len() as f64 / packed.len() as f64
+            );
+
+            group.bench_with_input(BenchmarkId::new("compress", &id), &source, |b, source| {
+                b.iter(|| {
+                    let mut result = Vec::with_capacity(source.len());
+                    codec
+                        .compress(&mut Cursor::new(source), &mut result)
+                        .unwrap();
+                    result
+                })
+            });
+            group.bench_with_input(BenchmarkId::new("decompress", &id), &packed, |b, packed| {
+                b.iter(|| {
+                    let mut result = Vec::with_capacity(source.len());
+                    codec.decompress(Cursor::new(packed), &mut result).unwrap();
+                    result
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cache_compression_benchmark);
+criterion_main!(benches);
@@ -1,14 +1,15 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::iter::FromIterator;
 use std::net::SocketAddr;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
@@ -25,12 +26,14 @@ use nickel::{
 };
 use sha2::digest::DynDigest;
 use sha2::{Digest, Sha256};
-use tempdir::TempDir;
 
-use octobuild::cluster::builder::{CompileRequest, CompileResponse};
+use octobuild::cluster::builder::{ClientScheduler, CompileRequest, CompileResponse};
 use octobuild::cluster::common::{
-    BuilderInfo, BuilderInfoUpdate, RPC_BUILDER_TASK, RPC_BUILDER_UPDATE, RPC_BUILDER_UPLOAD,
+    current_platform, BuilderInfo, BuilderInfoUpdate, ChunkInfo, PROTOCOL_VERSION,
+    RPC_BUILDER_ASSEMBLE, RPC_BUILDER_CHUNK, RPC_BUILDER_INFO, RPC_BUILDER_TASK,
+    RPC_BUILDER_UPDATE, RPC_BUILDER_UPLOAD,
 };
+use octobuild::cluster::discovery::BeaconSender;
 use octobuild::compiler::*;
 use octobuild::config::Config;
 use octobuild::io::memstream::MemStream;
@@ -49,20 +52,47 @@ struct BuilderService {
 struct BuilderState {
     name: String,
     shared: SharedState,
+    scheduler: ClientScheduler,
     precompiled_dir: PathBuf,
+    // Size limit of uploaded files and chunks kept for reuse.
+    upload_limit: u64,
+    // Total size of uploaded files and chunks.
+    upload_size: AtomicU64,
+    compiler: CompilerGroup,
     toolchains: HashMap<String, Arc<dyn Toolchain>>,
-    precompiled: Mutex<HashMap<String, Arc<PrecompiledFile>>>,
+    // Uploaded toolchain packages contain executables: run only allowed ones.
+    packages_enabled: bool,
+    package_allowlist: Vec<String>,
+    // Toolchains unpacked from uploaded packages by package hash.
+    packages: Mutex<HashMap<String, Arc<PackageSlot>>>,
+    // Locks of files uploading right now.
+    precompiled: Mutex<HashMap<String, Weak<PrecompiledFile>>>,
 }
 
 struct PrecompiledFile {
     lock: Mutex<()>,
 }
 
-const PRECOMPILED_SUFFIX: &str = ".pch";
+// Toolchain package is unpacked once under its own lock.
+type PackageSlot = Mutex<Option<Arc<dyn Toolchain>>>;
+
+// Uploaded files: precompiled headers and toolchain packages.
+const UPLOAD_SUFFIX: &str = ".upload";
+const CHUNK_SUFFIX: &str = ".chunk";
+const TOOLCHAINS_DIR: &str = "toolchains";
+// Coordinator request timeout (much less than builder information lifetime on coordinator).
+const COORDINATOR_TIMEOUT: Duration = Duration::from_millis(500);
 
 struct RpcBuilderTaskHandler(Arc<BuilderState>);
 
-struct RpcBuilderUploadHandler(Arc<BuilderState>);
+// Upload handler for files with specified suffix.
+struct RpcBuilderUploadHandler(Arc<BuilderState>, &'static str);
+
+struct RpcBuilderAssembleHandler(Arc<BuilderState>);
+
+struct RpcBuilderInfoHandler(Arc<BuilderState>);
+
+struct RpcBuilderCapacityHandler(Arc<BuilderState>);
 
 impl BuilderService {
     fn new() -> Self {
@@ -70,25 +100,64 @@ impl BuilderService {
         info!("Helper bind to address: {}", config.helper_bind);
 
         let temp_dir = create_temp_dir().expect("Can't create temporary directory");
+        let compiler = supported_compilers(&config, &temp_dir);
         let state = Arc::new(BuilderState {
             name: get_name(),
             shared: SharedState::new(&config).unwrap(),
-            toolchains: BuilderService::discovery_toolchains(&temp_dir),
-            precompiled_dir: config.cache_dir,
+            scheduler: ClientScheduler::new(config.process_limit, config.builder_client_limit),
+            toolchains: BuilderService::discovery_toolchains(&compiler),
+            compiler,
+            precompiled_dir: config.cache_dir.clone(),
+            // Uploads share cache directory with cache entries.
+            upload_limit: u64::from(config.cache_limit_mb) * 1024 * 1024 / 4,
+            upload_size: AtomicU64::new(0),
+            packages_enabled: config.builder_packages,
+            package_allowlist: config.builder_package_allowlist.clone(),
+            packages: Mutex::new(HashMap::new()),
             precompiled: Mutex::new(HashMap::new()),
         });
 
+        match cleanup_uploads(&state.precompiled_dir, state.upload_limit) {
+            Ok(size) => state.upload_size.store(size, Ordering::Relaxed),
+            Err(e) => info!("Can't cleanup uploaded files: {}", e),
+        }
+        // Allowlist changes only on restart.
+        if let Err(e) = cleanup_toolchains(&state.precompiled_dir.join(TOOLCHAINS_DIR), |hash| {
+            state.is_package_allowed(hash)
+        }) {
+            info!("Can't cleanup unpacked toolchains: {}", e);
+        }
+
         let mut http = Nickel::new();
         http.add_route(
             Method::Head,
             RPC_BUILDER_UPLOAD.to_string() + "/:hash",
-            RpcBuilderUploadHandler(state.clone()),
+            RpcBuilderUploadHandler(state.clone(), UPLOAD_SUFFIX),
         );
         http.post(
             RPC_BUILDER_UPLOAD.to_string() + "/:hash",
-            RpcBuilderUploadHandler(state.clone()),
+            RpcBuilderUploadHandler(state.clone(), UPLOAD_SUFFIX),
+        );
+        http.add_route(
+            Method::Head,
+            RPC_BUILDER_CHUNK.to_string() + "/:hash",
+            RpcBuilderUploadHandler(state.clone(), CHUNK_SUFFIX),
+        );
+        http.post(
+            RPC_BUILDER_CHUNK.to_string() + "/:hash",
+            RpcBuilderUploadHandler(state.clone(), CHUNK_SUFFIX),
+        );
+        http.post(
+            RPC_BUILDER_ASSEMBLE.to_string() + "/:hash",
+            RpcBuilderAssembleHandler(state.clone()),
         );
         http.post(RPC_BUILDER_TASK, RpcBuilderTaskHandler(state.clone()));
+        http.add_route(
+            Method::Head,
+            RPC_BUILDER_TASK.to_string() + "/:client",
+            RpcBuilderCapacityHandler(state.clone()),
+        );
+        http.get(RPC_BUILDER_INFO, RpcBuilderInfoHandler(state.clone()));
 
         let listener = http.listen(config.helper_bind).unwrap();
         info!("Helper local address: {}", listener.socket());
@@ -102,7 +171,8 @@ impl BuilderService {
         BuilderService {
             anoncer: Some(BuilderService::thread_anoncer(
                 state,
-                config.coordinator.unwrap(),
+                config.coordinator.clone(),
+                config.get_discovery_broadcast(),
                 done.clone(),
                 listener.socket(),
             )),
@@ -113,28 +183,58 @@ impl BuilderService {
 
     fn thread_anoncer(
         state: Arc<BuilderState>,
-        coordinator: reqwest::Url,
+        coordinators: Vec<reqwest::Url>,
+        broadcast: Option<SocketAddr>,
         done: Arc<AtomicBool>,
         endpoint: SocketAddr,
     ) -> JoinHandle<()> {
         thread::spawn(move || {
-            let info = BuilderInfoUpdate::new(BuilderInfo {
-                name: state.name.clone(),
-                version: version::VERSION.to_owned(),
-                endpoint: endpoint.to_string(),
-                toolchains: state.toolchain_names(),
+            let info = BuilderInfoUpdate::new(state.info(endpoint.to_string()));
+            let beacon = broadcast.and_then(|target| {
+                BeaconSender::new(target, &info)
+                    .map_err(|e| info!("Builder: can't create discovery beacon: {}", e))
+                    .ok()
+            });
+            // Answer probes from starting clients immediately.
+            let _responder = beacon.as_ref().and_then(|beacon| {
+                beacon
+                    .responder()
+                    .map_err(|e| info!("Builder: can't answer discovery probes: {}", e))
+                    .ok()
             });
 
-            let client = reqwest::blocking::Client::new();
+            let client = reqwest::blocking::Client::builder()
+                .connect_timeout(COORDINATOR_TIMEOUT)
+                .timeout(COORDINATOR_TIMEOUT)
+                .build()
+                .unwrap();
+            let payload = bincode::serialize(&info).unwrap();
             while !done.load(Ordering::Relaxed) {
-                match client
-                    .post(coordinator.join(RPC_BUILDER_UPDATE).unwrap())
-                    .body(bincode::serialize(&info).unwrap())
-                    .send()
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        info!("Builder: can't send info to coordinator: {}", e);
+                // Announce to all coordinators: any of them can be used by clients.
+                // Unavailable coordinator must not delay announce to other ones.
+                crossbeam::scope(|scope| {
+                    for coordinator in coordinators.iter() {
+                        let client = &client;
+                        let payload = &payload;
+                        scope.spawn(move |_| {
+                            if let Err(e) = client
+                                .post(coordinator.join(RPC_BUILDER_UPDATE).unwrap())
+                                .body(payload.clone())
+                                .send()
+                            {
+                                info!(
+                                    "Builder: can't send info to coordinator {}: {}",
+                                    coordinator, e
+                                );
+                            }
+                        });
+                    }
+                })
+                .unwrap();
+                // Announce to LAN for zero-config clients.
+                if let Some(ref beacon) = beacon {
+                    if let Err(e) = beacon.send() {
+                        info!("Builder: can't send discovery beacon: {}", e);
                     }
                 }
                 thread::sleep(Duration::from_secs(1));
@@ -142,8 +242,7 @@ impl BuilderService {
         })
     }
 
-    fn discovery_toolchains(temp_dir: &Arc<TempDir>) -> HashMap<String, Arc<dyn Toolchain>> {
-        let compiler = supported_compilers(temp_dir);
+    fn discovery_toolchains(compiler: &CompilerGroup) -> HashMap<String, Arc<dyn Toolchain>> {
         HashMap::from_iter(
             compiler
                 .discovery_toolchains()
@@ -181,9 +280,7 @@ impl<D> Middleware<D> for RpcBuilderTaskHandler {
                             StatusCode::BadRequest,
                         ));
                     }
-                    let path = state
-                        .precompiled_dir
-                        .join(hash.to_string() + PRECOMPILED_SUFFIX);
+                    let path = state.precompiled_dir.join(hash.to_string() + UPLOAD_SUFFIX);
                     if !path.exists() {
                         return Err(NickelError::new(
                             res,
@@ -195,18 +292,75 @@ impl<D> Middleware<D> for RpcBuilderTaskHandler {
                 }
                 None => None,
             };
+            let mut modules = Vec::with_capacity(request.modules.len());
+            for (name, hash) in request.modules.into_iter() {
+                if !is_valid_sha256(&hash) {
+                    return Err(NickelError::new(
+                        res,
+                        format!("Invalid hash value: {}", hash),
+                        StatusCode::BadRequest,
+                    ));
+                }
+                let path = state.precompiled_dir.join(hash.clone() + UPLOAD_SUFFIX);
+                if !path.exists() {
+                    return Err(NickelError::new(
+                        res,
+                        format!("Compiled module file not found: {}", hash),
+                        StatusCode::FailedDependency,
+                    ));
+                }
+                modules.push(ModuleFile { name, path });
+            }
             let compile_step: CompileStep = CompileStep {
                 output_object: None,
                 output_precompiled: None,
                 input_precompiled: precompiled,
+                input_modules: modules,
+                output_module: None,
                 args: request.args,
                 preprocessed: MemStream::from(request.preprocessed_data),
             };
 
-            let toolchain: Arc<dyn Toolchain> =
-                state.toolchains.get(&request.toolchain).unwrap().clone();
-            let response =
-                CompileResponse::from(toolchain.compile_memory(&state.shared, compile_step));
+            let toolchain: Arc<dyn Toolchain> = match state.toolchains.get(&request.toolchain) {
+                Some(toolchain) => toolchain.clone(),
+                None => match request.toolchain_package {
+                    Some(ref hash) if !state.is_package_allowed(hash) => {
+                        return Err(NickelError::new(
+                            res,
+                            format!("Toolchain package is not allowed: {}", hash),
+                            StatusCode::FailedDependency,
+                        ));
+                    }
+                    Some(ref hash) if is_valid_sha256(hash) => {
+                        match state.get_package(&request.toolchain, hash) {
+                            Ok(toolchain) => toolchain,
+                            Err(e) => {
+                                return Err(NickelError::new(
+                                    res,
+                                    format!("Can't load toolchain package {}: {}", hash, e),
+                                    StatusCode::FailedDependency,
+                                ));
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(NickelError::new(
+                            res,
+                            format!("Toolchain not found: {}", request.toolchain),
+                            StatusCode::NotFound,
+                        ));
+                    }
+                },
+            };
+            let response = match state.scheduler.acquire(&request.client) {
+                Some(_slot) => {
+                    CompileResponse::from(toolchain.compile_memory(&state.shared, compile_step))
+                }
+                None => {
+                    info!("Builder is busy for client: {}", request.client);
+                    CompileResponse::Busy
+                }
+            };
             let payload = bincode::serialize(&response).unwrap();
             res.set(StatusCode::Ok);
             res.set(MediaType::Bin);
@@ -245,11 +399,10 @@ impl<D> Middleware<D> for RpcBuilderUploadHandler {
             request.origin.method, hash, request.origin.remote_addr
         );
 
-        let path = state
-            .precompiled_dir
-            .join(hash.clone() + PRECOMPILED_SUFFIX);
+        let path = state.precompiled_dir.join(hash.clone() + self.1);
         if path.exists() {
-            // File is already uploaded
+            // File is already uploaded: reused file is evicted later.
+            drop(touch_file(&path));
             response.set(StatusCode::Accepted);
             return response.send("");
         }
@@ -270,81 +423,347 @@ impl<D> Middleware<D> for RpcBuilderUploadHandler {
         }
 
         // Receive uploading file.
-        let tempory = TempFile::wrap(&path.with_extension("tmp"));
-        let mut hasher = Sha256::new();
-        let mut temp = match File::create(tempory.path()) {
-            Ok(f) => f,
-            Err(e) => {
+        if let Err((status, message)) = receive_file(&mut request.origin, &path, &hash) {
+            return Err(NickelError::new(response, message, status));
+        }
+        drop(lock);
+        state.add_upload(&path);
+        response.set(StatusCode::Ok);
+        response.send("")
+    }
+}
+
+impl<D> Middleware<D> for RpcBuilderAssembleHandler {
+    fn invoke<'a, 'server>(
+        &'a self,
+        request: &mut Request<'a, 'server, D>,
+        mut response: Response<'a, D>,
+    ) -> MiddlewareResult<'a, D> {
+        let state = self.0.as_ref();
+        let hash = match request.param("hash") {
+            Some(v) => v.to_string(),
+            None => {
                 return Err(NickelError::new(
                     response,
-                    format!("Can't create file: {}", e),
-                    StatusCode::InternalServerError,
+                    "Hash is not defined",
+                    StatusCode::BadRequest,
                 ));
             }
         };
-        let mut buf: [u8; DEFAULT_BUF_SIZE] = [0; DEFAULT_BUF_SIZE];
-        let mut total_size = 0;
-        loop {
-            let size = match request.origin.read(&mut buf) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(NickelError::new(
-                        response,
-                        format!("Can't parse request body: {}", e),
-                        StatusCode::InternalServerError,
-                    ));
-                }
-            };
-            if size == 0 {
-                break;
-            }
-            total_size += size;
-            match temp.write(&buf[0..size]) {
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(NickelError::new(
-                        response,
-                        format!("Can't write file: {}", e),
-                        StatusCode::InternalServerError,
-                    ));
-                }
-            }
-            Digest::input(&mut hasher, &buf[0..size]);
-        }
-        if hex::encode(hasher.result()) != hash {
+        if !is_valid_sha256(&hash) {
             return Err(NickelError::new(
                 response,
-                format!("Content hash mismatch: {}, {}", hash, total_size),
+                format!("Invalid hash value: {}", hash),
                 StatusCode::BadRequest,
             ));
         }
-        drop(temp);
-
-        match fs::rename(tempory.path(), &path) {
-            Ok(_) => {}
+        let chunks: Vec<ChunkInfo> = match bincode::deserialize_from(&mut request.origin) {
+            Ok(v) => v,
             Err(e) => {
-                if !path.exists() {
-                    return Err(NickelError::new(
-                        response,
-                        format!("Can't rename file: {}", e),
-                        StatusCode::InternalServerError,
-                    ));
-                }
+                return Err(NickelError::new(
+                    response,
+                    format!("Can't parse chunk list: {}", e),
+                    StatusCode::BadRequest,
+                ));
             }
+        };
+        info!(
+            "Received assemble from ({}, {} chunks): {} ",
+            hash,
+            chunks.len(),
+            request.origin.remote_addr
+        );
+
+        let path = state.precompiled_dir.join(hash.clone() + UPLOAD_SUFFIX);
+        // Don't assemble same file in multiple threads.
+        let precompiled: Arc<PrecompiledFile> = state.get_precompiled(&hash);
+        let lock = precompiled.lock.lock().unwrap();
+        if path.exists() {
+            // File is already uploaded
+            response.set(StatusCode::Accepted);
+            return response.send("");
+        }
+
+        if let Err((status, message)) = assemble_file(&state.precompiled_dir, &chunks, &path, &hash)
+        {
+            return Err(NickelError::new(response, message, status));
         }
         drop(lock);
+        state.add_upload(&path);
         response.set(StatusCode::Ok);
         response.send("")
     }
 }
 
+impl<D> Middleware<D> for RpcBuilderInfoHandler {
+    fn invoke<'a, 'server>(
+        &'a self,
+        _: &mut Request<'a, 'server, D>,
+        mut response: Response<'a, D>,
+    ) -> MiddlewareResult<'a, D> {
+        // Endpoint is known only by caller.
+        let payload = bincode::serialize(&self.0.info(String::new())).unwrap();
+        response.set(StatusCode::Ok);
+        response.set(MediaType::Bin);
+        response.send(payload)
+    }
+}
+
+impl<D> Middleware<D> for RpcBuilderCapacityHandler {
+    fn invoke<'a, 'server>(
+        &'a self,
+        request: &mut Request<'a, 'server, D>,
+        mut response: Response<'a, D>,
+    ) -> MiddlewareResult<'a, D> {
+        let accepted = match request.param("client") {
+            Some(client) => self.0.scheduler.has_capacity(client),
+            None => false,
+        };
+        response.set(if accepted {
+            StatusCode::Ok
+        } else {
+            StatusCode::ServiceUnavailable
+        });
+        response.send("")
+    }
+}
+
+// Reader for sequence of chunk files.
+struct ChunkReader(VecDeque<File>);
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        while let Some(file) = self.0.front_mut() {
+            let size = file.read(buf)?;
+            if size > 0 {
+                return Ok(size);
+            }
+            self.0.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+// Receive file content to temporary file and move it to destination after hash validation.
+fn receive_file<R: Read>(
+    reader: &mut R,
+    path: &Path,
+    hash: &str,
+) -> Result<(), (StatusCode, String)> {
+    let tempory = TempFile::wrap(&path.with_extension("tmp"));
+    let mut hasher = Sha256::new();
+    let mut temp = File::create(tempory.path()).map_err(|e| {
+        (
+            StatusCode::InternalServerError,
+            format!("Can't create file: {}", e),
+        )
+    })?;
+    let mut buf: [u8; DEFAULT_BUF_SIZE] = [0; DEFAULT_BUF_SIZE];
+    let mut total_size = 0;
+    loop {
+        let size = reader.read(&mut buf).map_err(|e| {
+            (
+                StatusCode::InternalServerError,
+                format!("Can't parse request body: {}", e),
+            )
+        })?;
+        if size == 0 {
+            break;
+        }
+        total_size += size;
+        temp.write_all(&buf[0..size]).map_err(|e| {
+            (
+                StatusCode::InternalServerError,
+                format!("Can't write file: {}", e),
+            )
+        })?;
+        Digest::input(&mut hasher, &buf[0..size]);
+    }
+    if hex::encode(hasher.result()) != hash {
+        return Err((
+            StatusCode::BadRequest,
+            format!("Content hash mismatch: {}, {}", hash, total_size),
+        ));
+    }
+    drop(temp);
+
+    match fs::rename(tempory.path(), path) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            if !path.exists() {
+                return Err((
+                    StatusCode::InternalServerError,
+                    format!("Can't rename file: {}", e),
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+// Assemble file from uploaded chunks and verify full file hash.
+fn assemble_file(
+    dir: &Path,
+    chunks: &[ChunkInfo],
+    path: &Path,
+    hash: &str,
+) -> Result<(), (StatusCode, String)> {
+    let mut files = VecDeque::with_capacity(chunks.len());
+    for chunk in chunks.iter() {
+        if !is_valid_sha256(&chunk.hash) {
+            return Err((
+                StatusCode::BadRequest,
+                format!("Invalid chunk hash value: {}", chunk.hash),
+            ));
+        }
+        let chunk_path = dir.join(chunk.hash.clone() + CHUNK_SUFFIX);
+        // Reused chunk is evicted later.
+        drop(touch_file(&chunk_path));
+        match File::open(chunk_path) {
+            Ok(file) => files.push_back(file),
+            Err(_) => {
+                return Err((
+                    StatusCode::FailedDependency,
+                    format!("Chunk not found: {}", chunk.hash),
+                ));
+            }
+        }
+    }
+    receive_file(&mut ChunkReader(files), path, hash)
+}
+
+// Update modification time of file.
+fn touch_file(path: &Path) -> Result<(), io::Error> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut head = [0; 1];
+    if file.read(&mut head)? > 0 {
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&head)?;
+    }
+    Ok(())
+}
+
+// Remove least recently used uploaded files and chunks over size limit (with some headroom for
+// next uploads). Returns total size of remaining files.
+fn cleanup_uploads(dir: &Path, limit: u64) -> Result<u64, io::Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.ends_with(CHUNK_SUFFIX) && !name.ends_with(UPLOAD_SUFFIX) {
+            continue;
+        }
+        // File can be removed by concurrent cleanup.
+        if let Ok(stat) = entry.metadata() {
+            files.push((stat.modified()?, stat.len(), entry.path()));
+        }
+    }
+    files.sort_by_key(|file| Reverse(file.0));
+
+    let keep = limit / 4 * 3;
+    let mut total_size: u64 = 0;
+    let mut full = false;
+    for (_, size, path) in files.into_iter() {
+        full = full || total_size + size > keep;
+        if full {
+            drop(fs::remove_file(path));
+        } else {
+            total_size += size;
+        }
+    }
+    Ok(total_size)
+}
+
+// Remove unpacked toolchains which are not allowed anymore and unfinished unpacks.
+fn cleanup_toolchains<F: Fn(&str) -> bool>(dir: &Path, allowed: F) -> Result<(), io::Error> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !allowed(&name) {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
 fn is_valid_sha256(hash: &str) -> bool {
-    hex::decode(hash)
-        .ok()
-        .map_or(false, |v| v.len() == Sha256::new().output_size() * 2)
+    matches!(hex::decode(hash), Ok(ref v) if v.len() == Sha256::new().output_size())
 }
 
 impl BuilderState {
+    fn info(&self, endpoint: String) -> BuilderInfo {
+        BuilderInfo {
+            name: self.name.clone(),
+            version: version::VERSION.to_owned(),
+            endpoint,
+            toolchains: self.toolchain_names(),
+            // Clients send toolchain packages only to builders of the same platform.
+            platform: if self.packages_enabled {
+                current_platform()
+            } else {
+                String::new()
+            },
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+
+    fn is_package_allowed(&self, hash: &str) -> bool {
+        self.packages_enabled && self.package_allowlist.iter().any(|v| v == hash)
+    }
+
+    // Account uploaded file size and remove old uploads over the limit.
+    fn add_upload(&self, path: &Path) {
+        if let Ok(stat) = fs::metadata(path) {
+            self.upload_size.fetch_add(stat.len(), Ordering::Relaxed);
+        }
+        // Directory is scanned only when new uploads exceed the limit.
+        if self.upload_size.load(Ordering::Relaxed) > self.upload_limit {
+            match cleanup_uploads(&self.precompiled_dir, self.upload_limit) {
+                Ok(size) => self.upload_size.store(size, Ordering::Relaxed),
+                Err(e) => info!("Can't cleanup uploaded files: {}", e),
+            }
+        }
+    }
+
+    // Unpack uploaded toolchain package into private directory.
+    fn get_package(&self, identifier: &str, hash: &str) -> Result<Arc<dyn Toolchain>, io::Error> {
+        let slot = self
+            .packages
+            .lock()
+            .unwrap()
+            .entry(hash.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(None)))
+            .clone();
+        let mut package = slot.lock().unwrap();
+        if let Some(ref toolchain) = *package {
+            return Ok(toolchain.clone());
+        }
+        let root = self.precompiled_dir.join(TOOLCHAINS_DIR).join(hash);
+        if !root.exists() {
+            let archive = self.precompiled_dir.join(hash.to_string() + UPLOAD_SUFFIX);
+            let temp = self
+                .precompiled_dir
+                .join(TOOLCHAINS_DIR)
+                .join(hash.to_string() + ".tmp");
+            if temp.exists() {
+                fs::remove_dir_all(&temp)?;
+            }
+            fs::create_dir_all(&temp)?;
+            tar::Archive::new(File::open(archive)?).unpack(&temp)?;
+            fs::rename(&temp, &root)?;
+        }
+        let toolchain = self
+            .compiler
+            .load_package(identifier, &root)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported package"))?;
+        info!("Loaded toolchain package: {} ({})", identifier, hash);
+        *package = Some(toolchain.clone());
+        Ok(toolchain)
+    }
+
     fn toolchain_names(&self) -> Vec<String> {
         let mut names: Vec<String> = self.toolchains.keys().cloned().collect();
         names.sort();
@@ -352,16 +771,17 @@ impl BuilderState {
     }
 
     fn get_precompiled(&self, hash: &str) -> Arc<PrecompiledFile> {
-        self.precompiled
-            .lock()
-            .unwrap()
-            .entry(hash.to_string())
-            .or_insert_with(|| {
-                Arc::new(PrecompiledFile {
-                    lock: Mutex::new(()),
-                })
-            })
-            .clone()
+        let mut precompiled = self.precompiled.lock().unwrap();
+        if let Some(file) = precompiled.get(hash).and_then(Weak::upgrade) {
+            return file;
+        }
+        // Forget locks of finished uploads.
+        precompiled.retain(|_, file| file.strong_count() > 0);
+        let file = Arc::new(PrecompiledFile {
+            lock: Mutex::new(()),
+        });
+        precompiled.insert(hash.to_string(), Arc::downgrade(&file));
+        file
     }
 }
 
@@ -415,3 +835,67 @@ fn main() {
         })
         .unwrap();
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Cursor;
+
+    use nickel::status::StatusCode;
+    use tempdir::TempDir;
+
+    use octobuild::cluster::common::Chunker;
+
+    use super::{assemble_file, cleanup_toolchains, cleanup_uploads, CHUNK_SUFFIX, UPLOAD_SUFFIX};
+
+    #[test]
+    fn test_assemble_file() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let chunker = Chunker {
+            min_size: 256,
+            max_size: 4096,
+            mask: (1 << 10) - 1,
+        };
+        let chunks = chunker.split(&mut Cursor::new(&data)).unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in chunks.iter() {
+            let begin = chunk.offset as usize;
+            let end = begin + chunk.size as usize;
+            fs::write(
+                temp.path().join(chunk.hash.clone() + CHUNK_SUFFIX),
+                &data[begin..end],
+            )
+            .unwrap();
+        }
+        let hash = octobuild::utils::hash_stream(&mut Cursor::new(&data)).unwrap();
+        let path = temp.path().join(hash.clone() + UPLOAD_SUFFIX);
+
+        // Chunks in wrong order don't match file hash.
+        let reversed: Vec<_> = chunks.iter().rev().cloned().collect();
+        let error = assemble_file(temp.path(), &reversed, &path, &hash).unwrap_err();
+        assert_eq!(error.0, StatusCode::BadRequest);
+        assert!(!path.exists());
+
+        assemble_file(temp.path(), &chunks, &path, &hash).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data);
+
+        // Removed chunk can't be used for next file.
+        assert_eq!(cleanup_uploads(temp.path(), 0).unwrap(), 0);
+        assert!(!path.exists());
+        let error = assemble_file(temp.path(), &chunks, &path, &hash).unwrap_err();
+        assert_eq!(error.0, StatusCode::FailedDependency);
+    }
+
+    #[test]
+    fn test_cleanup_toolchains() {
+        let temp = TempDir::new("octobuild").unwrap();
+        for name in &["allowed", "removed", "allowed.tmp"] {
+            fs::create_dir_all(temp.path().join(name).join("bin")).unwrap();
+        }
+        cleanup_toolchains(temp.path(), |hash| hash == "allowed").unwrap();
+        assert!(temp.path().join("allowed").exists());
+        assert!(!temp.path().join("removed").exists());
+        assert!(!temp.path().join("allowed.tmp").exists());
+    }
+}
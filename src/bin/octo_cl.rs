@@ -3,5 +3,7 @@ use octobuild::vs::compiler::VsCompiler;
 use std::process;
 
 fn main() {
-    process::exit(simple_compile("cl.exe", |_| VsCompiler::default()))
+    process::exit(simple_compile("cl.exe", |config| {
+        VsCompiler::default().map(|compiler| compiler.with_z7(config.msvc_z7))
+    }))
 }
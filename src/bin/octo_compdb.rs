@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Error, ErrorKind, Write};
+use std::process;
+
+use clap::{App, Arg};
+use glob::Pattern;
+
+use octobuild::cluster::client::RemoteCompiler;
+use octobuild::compdb;
+use octobuild::compiler::SharedState;
+use octobuild::config::Config;
+use octobuild::simple::{create_temp_dir, supported_compilers};
+use octobuild::version::{AUTHORS, VERSION};
+use octobuild::worker::{execute_graph, BuildResult};
+
+const DATABASE: &str = "database";
+const KEEP_GOING: &str = "keep-going";
+const FILES: &str = "files";
+
+fn main() {
+    let matches = App::new("octo_compdb")
+        .version(VERSION)
+        .author(AUTHORS)
+        .about("Compile all files from compilation database (compile_commands.json)")
+        .arg(
+            Arg::with_name(DATABASE)
+                .short("p")
+                .long("database")
+                .value_name("file")
+                .default_value("compile_commands.json")
+                .help("Compilation database path"),
+        )
+        .arg(
+            Arg::with_name(KEEP_GOING)
+                .short("k")
+                .long("keep-going")
+                .help("Continue build after failed files"),
+        )
+        .arg(
+            Arg::with_name(FILES)
+                .multiple(true)
+                .help("Compile only source files matched by glob patterns"),
+        )
+        .get_matches();
+
+    let database = matches.value_of(DATABASE).unwrap();
+    let files = matches.values_of_lossy(FILES).unwrap_or_default();
+    process::exit(
+        match execute(database, &files, matches.is_present(KEEP_GOING)) {
+            Ok(result) => result.unwrap_or(501),
+            Err(e) => {
+                println!("FATAL ERROR: {}", e);
+                500
+            }
+        },
+    )
+}
+
+fn execute(database: &str, files: &[String], keep_going: bool) -> Result<Option<i32>, Error> {
+    let patterns = files
+        .iter()
+        .map(|mask| Pattern::new(mask).map_err(|e| Error::new(ErrorKind::InvalidInput, e)))
+        .collect::<Result<Vec<Pattern>, Error>>()?;
+    let commands = compdb::parser::parse(BufReader::new(File::open(database)?))?;
+    let commands = compdb::parser::filter(commands, &patterns);
+
+    let config = Config::new()?;
+    let state = SharedState::new(&config)?;
+    let compiler = RemoteCompiler::new(&config, supported_compilers(&config, &create_temp_dir()?));
+    let graph = compdb::parser::create_graph(&compiler, &commands)?;
+    let result = execute_graph(
+        &state,
+        graph,
+        config.process_limit,
+        keep_going,
+        print_task_result,
+    );
+    let _ = state.cache.cleanup();
+    println!("{}", state.statistic);
+    result
+}
+
+fn print_task_result(result: BuildResult) -> Result<(), Error> {
+    println!(
+        "#{} {}/{}: {}",
+        result.worker, result.completed, result.total, result.task.title
+    );
+    if let Ok(ref output) = result.result {
+        io::stdout().write_all(&output.stdout)?;
+        io::stderr().write_all(&output.stderr)?;
+    }
+    Ok(())
+}
@@ -1,45 +1,298 @@
-use std::net::{IpAddr, SocketAddr};
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, RwLock};
+use std::thread;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 use daemon::Daemon;
 use daemon::DaemonRunner;
 use daemon::State;
-use log::info;
+use log::{info, warn};
 use nickel::status::StatusCode;
 use nickel::{
-    HttpRouter, MediaType, Middleware, MiddlewareResult, Nickel, NickelError, Request, Response,
+    HttpRouter, ListeningServer, MediaType, Middleware, MiddlewareResult, Nickel, NickelError,
+    Request, Response,
 };
 
 use octobuild::cluster::common::{
-    BuilderInfo, BuilderInfoUpdate, RPC_BUILDER_LIST, RPC_BUILDER_UPDATE,
+    BuilderInfo, BuilderInfoUpdate, RPC_BUILDER_INFO, RPC_BUILDER_LIST, RPC_BUILDER_UPDATE,
+    RPC_COORDINATOR_SYNC,
 };
 use octobuild::config::Config;
+use octobuild::io::tempfile::TempFile;
+
+// Builder information lifetime without updates.
+const BUILDER_TIMEOUT: Duration = Duration::from_secs(5);
+// Restored builder information lifetime (gives builders time to send heartbeat).
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(30);
+// Peer synchronization and state saving interval.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+const STATE_FILE_NAME: &str = "coordinator.state";
 
 struct BuilderState {
     pub guid: String,
     pub info: BuilderInfo,
     pub timeout: Instant,
+    // Builder information is received from builder itself (not from peer or static list)
+    pub direct: bool,
 }
 
 struct CoordinatorState {
     builders: RwLock<Vec<BuilderState>>,
+    // Direct builder list is changed since last save.
+    dirty: AtomicBool,
+}
+
+struct CoordinatorService {
+    done: Arc<AtomicBool>,
+    listener: Option<ListeningServer>,
+    syncer: Option<JoinHandle<()>>,
 }
 
 impl CoordinatorState {
     pub fn new() -> Self {
         CoordinatorState {
             builders: RwLock::new(Vec::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn update(&self, update: BuilderInfoUpdate, timeout: Duration, direct: bool) {
+        let mut holder = self.builders.write().unwrap();
+        let now = Instant::now();
+        // Same builder can be known by heartbeat and by static list.
+        let same =
+            |e: &BuilderState| (e.guid == update.guid) || (e.info.endpoint == update.info.endpoint);
+        // Don't replace information received from builder itself.
+        if !direct
+            && holder
+                .iter()
+                .any(|e| e.direct && same(e) && (e.timeout >= now))
+        {
+            return;
+        }
+        let changed = direct
+            && !holder
+                .iter()
+                .any(|e| e.direct && (e.guid == update.guid) && (e.info == update.info));
+        let expired = holder.iter().any(|e| e.direct && (e.timeout < now));
+        holder.retain(|e| !same(e) && (e.timeout >= now));
+        if changed || expired {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        holder.push(BuilderState {
+            guid: update.guid,
+            info: update.info,
+            timeout: now + timeout,
+            direct,
+        });
+    }
+
+    fn builders(&self) -> Vec<BuilderInfo> {
+        let holder = self.builders.read().unwrap();
+        let now = Instant::now();
+        holder
+            .iter()
+            .filter(|e| e.timeout >= now)
+            .map(|e| e.info.clone())
+            .collect()
+    }
+
+    fn direct_builders(&self) -> Vec<BuilderInfoUpdate> {
+        let holder = self.builders.read().unwrap();
+        let now = Instant::now();
+        holder
+            .iter()
+            .filter(|e| e.direct && (e.timeout >= now))
+            .map(|e| BuilderInfoUpdate {
+                guid: e.guid.clone(),
+                info: e.info.clone(),
+            })
+            .collect()
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = TempFile::wrap(&path.with_extension("tmp"));
+        bincode::serialize_into(
+            BufWriter::new(File::create(temp.path())?),
+            &self.direct_builders(),
+        )
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+        fs::rename(temp.path(), path)
+    }
+
+    fn save_if_dirty(&self, path: &Path) -> Result<(), Error> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
         }
+        let result = self.save(path);
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn load(&self, path: &Path) -> Result<(), Error> {
+        let builders: Vec<BuilderInfoUpdate> =
+            bincode::deserialize_from(BufReader::new(File::open(path)?))
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // Restored builders are not shared with peers until their own heartbeat.
+        for update in builders.into_iter() {
+            self.update(update, RESTORE_TIMEOUT, false);
+        }
+        Ok(())
     }
 }
 
+impl CoordinatorService {
+    fn new() -> Self {
+        let config = Config::new().unwrap();
+        info!("Coordinator bind to address: {}", config.coordinator_bind);
+
+        let state = Arc::new(CoordinatorState::new());
+        let state_path = config.cache_dir.join(STATE_FILE_NAME);
+        match state.load(&state_path) {
+            Ok(_) => info!("Coordinator: builder table restored"),
+            Err(e) => info!("Coordinator: can't restore builder table: {}", e),
+        }
+
+        let mut http = Nickel::new();
+        http.get(RPC_BUILDER_LIST, RpcAgentListHandler(state.clone()));
+        http.get(
+            RPC_COORDINATOR_SYNC,
+            RpcCoordinatorSyncHandler(state.clone()),
+        );
+        http.post(RPC_BUILDER_UPDATE, RpcAgentUpdateHandler(state.clone()));
+
+        let listener = http.listen(config.coordinator_bind).unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        CoordinatorService {
+            syncer: Some(CoordinatorService::thread_syncer(
+                state,
+                config.coordinator_peers,
+                config.static_builders,
+                state_path,
+                done.clone(),
+            )),
+            done,
+            listener: Some(listener),
+        }
+    }
+
+    fn thread_syncer(
+        state: Arc<CoordinatorState>,
+        peers: Vec<reqwest::Url>,
+        static_builders: Vec<String>,
+        state_path: PathBuf,
+        done: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let client = reqwest::blocking::Client::builder()
+                .timeout(SYNC_INTERVAL)
+                .build()
+                .unwrap();
+            while !done.load(Ordering::Relaxed) {
+                for peer in peers.iter() {
+                    match receive_peer_builders(&client, peer) {
+                        Ok(builders) => {
+                            for update in builders.into_iter() {
+                                state.update(update, BUILDER_TIMEOUT, false);
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Coordinator: can't sync with peer {}: {}", peer, e);
+                        }
+                    }
+                }
+                for endpoint in static_builders.iter() {
+                    match receive_static_builder(&client, endpoint) {
+                        Ok(info) => {
+                            state.update(
+                                BuilderInfoUpdate {
+                                    guid: format!("static:{}", endpoint),
+                                    info,
+                                },
+                                BUILDER_TIMEOUT,
+                                false,
+                            );
+                        }
+                        Err(e) => {
+                            warn!("Coordinator: can't get builder {} info: {}", endpoint, e);
+                        }
+                    }
+                }
+                if let Err(e) = state.save_if_dirty(&state_path) {
+                    warn!("Coordinator: can't save builder table: {}", e);
+                }
+                thread::sleep(SYNC_INTERVAL);
+            }
+        })
+    }
+}
+
+impl Drop for CoordinatorService {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(t) = self.syncer.take() {
+            t.join().unwrap();
+        }
+        if let Some(t) = self.listener.take() {
+            t.detach();
+        }
+    }
+}
+
+fn receive_peer_builders(
+    client: &reqwest::blocking::Client,
+    peer: &reqwest::Url,
+) -> Result<Vec<BuilderInfoUpdate>, Error> {
+    let mut response = client
+        .get(peer.join(RPC_COORDINATOR_SYNC).unwrap())
+        .send()
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+    bincode::deserialize_from(&mut response).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn receive_static_builder(
+    client: &reqwest::blocking::Client,
+    endpoint: &str,
+) -> Result<BuilderInfo, Error> {
+    let url = reqwest::Url::parse(&format!("http://{}", endpoint))
+        .and_then(|url| url.join(RPC_BUILDER_INFO))
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let mut response = client
+        .get(url)
+        .send()
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+    let mut info: BuilderInfo = bincode::deserialize_from(&mut response)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    // Use resolved address to match builder heartbeat endpoint.
+    info.endpoint = endpoint
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .map_or_else(|| endpoint.to_string(), |addr| addr.to_string());
+    Ok(info)
+}
+
 struct RpcAgentUpdateHandler(Arc<CoordinatorState>);
 
 struct RpcAgentListHandler(Arc<CoordinatorState>);
 
+struct RpcCoordinatorSyncHandler(Arc<CoordinatorState>);
+
 impl<D> Middleware<D> for RpcAgentUpdateHandler {
     fn invoke<'a, 'server>(
         &'a self,
@@ -63,19 +316,9 @@ impl<D> Middleware<D> for RpcAgentUpdateHandler {
                 SocketAddr::new(request.origin.remote_addr.ip(), endpoint.port()).to_string();
         }
 
-        let payload: Vec<u8>;
         // Update information.
-        {
-            let mut holder = self.0.builders.write().unwrap();
-            let now = Instant::now();
-            holder.retain(|e| (e.guid != update.guid) && (e.timeout >= now));
-            payload = bincode::serialize(&update.info).unwrap();
-            holder.push(BuilderState {
-                guid: update.guid,
-                info: update.info,
-                timeout: now + Duration::from_secs(5),
-            });
-        }
+        let payload: Vec<u8> = bincode::serialize(&update.info).unwrap();
+        self.0.update(update, BUILDER_TIMEOUT, true);
 
         response.set(StatusCode::Ok);
         response.set(MediaType::Bin);
@@ -89,18 +332,22 @@ impl<D> Middleware<D> for RpcAgentListHandler {
         _: &mut Request<'a, 'server, D>,
         mut response: Response<'a, D>,
     ) -> MiddlewareResult<'a, D> {
-        let holder = self.0.builders.read().unwrap();
-        let now = Instant::now();
-        let builders: Vec<&BuilderInfo> = holder
-            .iter()
-            .filter_map(|e| {
-                if e.timeout >= now {
-                    Some(&e.info)
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let builders = self.0.builders();
+
+        response.set(StatusCode::Ok);
+        response.set(MediaType::Bin);
+        response.send(bincode::serialize(&builders).unwrap())
+    }
+}
+
+impl<D> Middleware<D> for RpcCoordinatorSyncHandler {
+    fn invoke<'a, 'server>(
+        &'a self,
+        _: &mut Request<'a, 'server, D>,
+        mut response: Response<'a, D>,
+    ) -> MiddlewareResult<'a, D> {
+        // Share only own builders to avoid endless resharing between peers.
+        let builders = self.0.direct_builders();
 
         response.set(StatusCode::Ok);
         response.set(MediaType::Bin);
@@ -125,21 +372,11 @@ fn main() {
             octobuild::utils::init_logger();
 
             info!("Coordinator started.");
-            let mut web = None;
+            let mut coordinator = None;
             for signal in rx.iter() {
                 match signal {
                     State::Start => {
-                        let config = Config::new().unwrap();
-                        info!("Coordinator bind to address: {}", config.coordinator_bind);
-
-                        let state = Arc::new(CoordinatorState::new());
-                        let mut http = Nickel::new();
-                        http.get(RPC_BUILDER_LIST, RpcAgentListHandler(state.clone()));
-                        http.post(RPC_BUILDER_UPDATE, RpcAgentUpdateHandler(state.clone()));
-
-                        let listener = http.listen(config.coordinator_bind).unwrap();
-
-                        web = Some(listener);
+                        coordinator = Some(CoordinatorService::new());
                         info!("Coordinator: Ready");
                     }
                     State::Reload => {
@@ -147,9 +384,7 @@ fn main() {
                     }
                     State::Stop => {
                         info!("Coordinator: Stoping");
-                        if let Some(v) = web.take() {
-                            v.detach();
-                        }
+                        coordinator.take();
                         info!("Coordinator: Stoped");
                     }
                 };
@@ -158,3 +393,83 @@ fn main() {
         })
         .unwrap();
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tempdir::TempDir;
+
+    use octobuild::cluster::common::{BuilderInfo, BuilderInfoUpdate};
+
+    use super::CoordinatorState;
+
+    fn builder_update(guid: &str, endpoint: &str) -> BuilderInfoUpdate {
+        BuilderInfoUpdate {
+            guid: guid.to_string(),
+            info: BuilderInfo {
+                name: guid.to_string(),
+                endpoint: endpoint.to_string(),
+                version: "1.0".to_string(),
+                toolchains: vec!["clang".to_string()],
+                platform: "linux-x86_64".to_string(),
+                protocol: 2,
+            },
+        }
+    }
+
+    #[test]
+    fn test_coordinator_merge() {
+        let timeout = Duration::from_secs(60);
+        let state = CoordinatorState::new();
+        state.update(builder_update("static:a", "10.0.0.1:3001"), timeout, false);
+        state.update(builder_update("b", "10.0.0.2:3001"), timeout, false);
+        assert_eq!(state.builders().len(), 2);
+        assert!(state.direct_builders().is_empty());
+
+        // Heartbeat replaces static entry with the same endpoint.
+        state.update(builder_update("a", "10.0.0.1:3001"), timeout, true);
+        let names: Vec<String> = state.builders().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, vec!["b", "a"]);
+
+        // Static and peer information doesn't replace heartbeat.
+        state.update(builder_update("static:a", "10.0.0.1:3001"), timeout, false);
+        state.update(builder_update("a", "10.0.0.3:3001"), timeout, false);
+        let direct = state.direct_builders();
+        assert_eq!(direct.len(), 1);
+        assert_eq!(direct[0].info.endpoint, "10.0.0.1:3001");
+        assert_eq!(state.builders().len(), 2);
+    }
+
+    #[test]
+    fn test_coordinator_save_load() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let path = temp.path().join("coordinator.state");
+        let timeout = Duration::from_secs(60);
+        let state = CoordinatorState::new();
+        state.update(builder_update("a", "10.0.0.1:3001"), timeout, true);
+        state.update(builder_update("b", "10.0.0.2:3001"), timeout, false);
+        state.save_if_dirty(&path).unwrap();
+        assert!(path.exists());
+
+        // Unchanged state is not saved again.
+        std::fs::remove_file(&path).unwrap();
+        state.update(builder_update("a", "10.0.0.1:3001"), timeout, true);
+        state.save_if_dirty(&path).unwrap();
+        assert!(!path.exists());
+        state.save(&path).unwrap();
+
+        // Only direct builders are saved and restored as non-direct.
+        let restored = CoordinatorState::new();
+        restored.load(&path).unwrap();
+        assert_eq!(
+            restored.builders(),
+            vec![builder_update("a", "10.0.0.1:3001").info]
+        );
+        assert!(restored.direct_builders().is_empty());
+        restored.save_if_dirty(&path).unwrap();
+        let reloaded = CoordinatorState::new();
+        reloaded.load(&path).unwrap();
+        assert_eq!(reloaded.builders().len(), 1);
+    }
+}
@@ -1,13 +1,16 @@
 #![allow(non_snake_case)]
 
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{BufReader, Error, ErrorKind, Write};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use petgraph::graph::NodeIndex;
 use petgraph::{EdgeDirection, Graph};
@@ -17,6 +20,8 @@ use lazy_static::lazy_static;
 use octobuild::cluster::client::RemoteCompiler;
 use octobuild::compiler::*;
 use octobuild::config::Config;
+use octobuild::io::fallback::FallbackStatistic;
+use octobuild::io::trace::BuildTrace;
 use octobuild::simple::create_temp_dir;
 use octobuild::simple::supported_compilers;
 use octobuild::version;
@@ -26,30 +31,174 @@ use octobuild::worker::{BuildAction, BuildGraph, BuildResult, BuildTask};
 use octobuild::xg;
 use octobuild::xg::parser::{XgGraph, XgNode};
 
+// XGE compatible command line options.
+#[derive(Default)]
+struct ConsoleOptions {
+    // Build task files
+    files: Vec<PathBuf>,
+    // Ignore cached results (/Rebuild)
+    rebuild: bool,
+    // Remove task outputs instead of build (/Clean)
+    clean: bool,
+    // Don't print header (/NoLogo)
+    no_logo: bool,
+    // Print where task was executed (/ShowAgent)
+    show_agent: bool,
+    // Print task durations (/ShowTime)
+    show_time: bool,
+    // Continue build after failed tasks (/KeepGoing)
+    keep_going: bool,
+    // Copy console output to file (/Out=<file>)
+    out: Option<PathBuf>,
+    // Override process limit (/MaxCPUs=<count>)
+    max_cpus: Option<usize>,
+    // Build title (/Title=<title>)
+    title: Option<String>,
+    // Chrome trace output (/Trace=<file> or OCTOBUILD_TRACE)
+    trace: Option<PathBuf>,
+    // Print prepared tasks instead of build (/DryRun)
+    dry_run: bool,
+    // Write prepared graph in Graphviz DOT format (/Dot=<file>)
+    dot: Option<PathBuf>,
+    // Write fallback reasons as JSON (/Fallbacks=<file>)
+    fallbacks: Option<PathBuf>,
+}
+
+// Console output with optional copy to log file.
+struct Console {
+    log: Option<Mutex<File>>,
+}
+
 fn main() {
-    println!("xgConsole ({}):", version::full_version());
     let args = Vec::from_iter(env::args());
-    for arg in args.iter() {
-        println!("  {}", arg);
-    }
     if args.len() == 1 {
+        println!("xgConsole ({}):", version::full_version());
         println!();
         Config::help();
         return;
     }
 
-    process::exit(match execute(&args[1..]) {
+    let (console, result) = match ConsoleOptions::parse(&args[1..])
+        .and_then(|options| Console::new(options.out.as_ref()).map(|console| (options, console)))
+    {
+        Ok((options, console)) => {
+            let result = run(&args, &options, &console);
+            (Some(console), result)
+        }
+        Err(e) => (None, Err(e)),
+    };
+    process::exit(match result {
         Ok(result) => match result {
             Some(r) => r,
             None => 501,
         },
         Err(e) => {
-            println!("FATAL ERROR: {}", e);
+            let message = format!("FATAL ERROR: {}", e);
+            // Fatal error is also copied to log file (/Out).
+            match console {
+                Some(console) => drop(console.println(&message)),
+                None => println!("{}", message),
+            }
             500
         }
     })
 }
 
+fn run(args: &[String], options: &ConsoleOptions, console: &Console) -> Result<Option<i32>, Error> {
+    if !options.no_logo {
+        console.println(&format!("xgConsole ({}):", version::full_version()))?;
+        for arg in args.iter() {
+            console.println(&format!("  {}", arg))?;
+        }
+    }
+    execute(options, console)
+}
+
+impl ConsoleOptions {
+    fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut options = ConsoleOptions::default();
+        for arg in args.iter() {
+            if !is_flag(arg) {
+                options.files = expand_files(options.files, arg);
+                continue;
+            }
+            let (name, value) = match arg.find('=') {
+                Some(pos) => (&arg[1..pos], Some(&arg[pos + 1..])),
+                None => (&arg[1..], None),
+            };
+            match (name.to_lowercase().as_str(), value) {
+                ("rebuild", None) => options.rebuild = true,
+                ("clean", None) => options.clean = true,
+                ("nologo", None) => options.no_logo = true,
+                ("showagent", None) => options.show_agent = true,
+                ("showtime", None) => options.show_time = true,
+                ("keepgoing", None) => options.keep_going = true,
+                ("dryrun", None) => options.dry_run = true,
+                ("fallbacks", Some(value)) if !value.is_empty() => {
+                    options.fallbacks = Some(PathBuf::from(value))
+                }
+                ("dot", Some(value)) if !value.is_empty() => {
+                    options.dot = Some(PathBuf::from(value))
+                }
+                ("out", Some(value)) if !value.is_empty() => {
+                    options.out = Some(PathBuf::from(value))
+                }
+                ("maxcpus", Some(value)) => match value.parse::<usize>() {
+                    Ok(count) if count > 0 => options.max_cpus = Some(count),
+                    _ => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Invalid CPU count: {}", arg),
+                        ));
+                    }
+                },
+                ("title", Some(value)) => options.title = Some(value.to_string()),
+                ("trace", Some(value)) if !value.is_empty() => {
+                    options.trace = Some(PathBuf::from(value))
+                }
+                // Other XGE flags are not supported and ignored.
+                _ => {}
+            }
+        }
+        if options.trace.is_none() {
+            options.trace = env::var_os("OCTOBUILD_TRACE").map(PathBuf::from);
+        }
+        Ok(options)
+    }
+}
+
+impl Console {
+    fn new(path: Option<&PathBuf>) -> Result<Self, Error> {
+        Ok(Console {
+            log: match path {
+                Some(path) => Some(Mutex::new(File::create(path)?)),
+                None => None,
+            },
+        })
+    }
+
+    fn println(&self, line: &str) -> Result<(), Error> {
+        self.write_out(format!("{}\n", line).as_bytes())
+    }
+
+    fn write_out(&self, data: &[u8]) -> Result<(), Error> {
+        io::stdout().write_all(data)?;
+        self.write_log(data)
+    }
+
+    fn write_err(&self, data: &[u8]) -> Result<(), Error> {
+        io::stderr().write_all(data)?;
+        self.write_log(data)
+    }
+
+    fn write_log(&self, data: &[u8]) -> Result<(), Error> {
+        match self.log {
+            Some(ref log) => log.lock().unwrap().write_all(data),
+            None => Ok(()),
+        }
+    }
+}
+
 fn is_flag(arg: &str) -> bool {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"^/\w+([=].*)?$").unwrap();
@@ -121,68 +270,216 @@ fn expand_files(mut files: Vec<PathBuf>, arg: &str) -> Vec<PathBuf> {
     files
 }
 
-fn execute(args: &[String]) -> Result<Option<i32>, Error> {
-    let config = Config::new()?;
-    let state = SharedState::new(&config)?;
-    let compiler = RemoteCompiler::new(
-        &config.coordinator,
-        supported_compilers(&create_temp_dir()?),
-    );
-    let files = args
-        .iter()
-        .filter(|a| !is_flag(a))
-        .fold(Vec::new(), |state, a| expand_files(state, &a));
-    if files.is_empty() {
+fn execute(options: &ConsoleOptions, console: &Console) -> Result<Option<i32>, Error> {
+    let mut config = Config::new()?;
+    if let Some(max_cpus) = options.max_cpus {
+        config.process_limit = max_cpus;
+    }
+    let mut state = SharedState::new(&config)?;
+    state.cache.set_rebuild(options.rebuild);
+    let compiler = RemoteCompiler::new(&config, supported_compilers(&config, &create_temp_dir()?));
+    if options.files.is_empty() {
         return Err(Error::new(
             ErrorKind::InvalidInput,
             "Build task files not found",
         ));
     }
+    if let Some(ref title) = options.title {
+        console.println(title)?;
+    }
 
     let mut graph = Graph::new();
-    for arg in files.iter() {
+    let mut fallbacks = HashMap::new();
+    for arg in options.files.iter() {
         let file = File::open(&Path::new(arg))?;
         xg::parser::parse(&mut graph, BufReader::new(file))?;
     }
-    let build_graph = validate_graph(graph).and_then(|graph| prepare_graph(&compiler, graph))?;
+    let build_graph = validate_graph(graph).and_then(|graph| {
+        prepare_graph(
+            console,
+            &compiler,
+            &compiler.remote_only(),
+            compiler.local(),
+            graph,
+            &mut fallbacks,
+        )
+    })?;
+    if let Some(ref path) = options.dot {
+        write_dot(&build_graph, &fallbacks, File::create(path)?)?;
+        console.println(&format!("Build graph: {}", path.display()))?;
+    }
+    let mut fallback_statistic = FallbackStatistic::new();
+    for (index, error) in fallbacks.iter() {
+        fallback_statistic.add(&build_graph[*index].title, error);
+    }
+    if let Some(ref path) = options.fallbacks {
+        fallback_statistic.write_json(File::create(path)?)?;
+    }
+    if options.dry_run {
+        print_dry_run(&build_graph, &fallbacks, console)?;
+        print_fallbacks(&fallback_statistic, console)?;
+        return Ok(Some(0));
+    }
+    if options.clean {
+        return clean_graph(&build_graph, console);
+    }
 
-    let result = execute_graph(&state, build_graph, config.process_limit, print_task_result);
+    let total = build_graph.node_count();
+    let executed = Mutex::new(0);
+    let failures = Mutex::new(Vec::new());
+    let trace = BuildTrace::new();
+    let result = execute_graph(
+        &state,
+        build_graph,
+        config.process_limit,
+        options.keep_going,
+        |result| {
+            *executed.lock().unwrap() += 1;
+            if let Some(failure) = task_failure(&result) {
+                failures.lock().unwrap().push(failure);
+            }
+            if options.trace.is_some() {
+                trace.record(&result);
+            }
+            print_task_result(options, console, result)
+        },
+    );
     let _ = state.cache.cleanup();
-    println!("{}", state.statistic.to_string());
+    if let Some(ref path) = options.trace {
+        console.println(&match trace.save(path) {
+            Ok(_) => format!("Build trace: {}", path.display()),
+            Err(e) => format!("Can't write build trace {}: {}", path.display(), e),
+        })?;
+    }
+    console.println(&state.statistic.to_string())?;
+    print_fallbacks(&fallback_statistic, console)?;
+    let failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        console.println(&format!("Failed tasks ({}):", failures.len()))?;
+        for failure in failures.iter() {
+            console.println(&format!("  {}", failure))?;
+        }
+        let skipped = total - executed.into_inner().unwrap();
+        if skipped > 0 {
+            console.println(&format!("Skipped tasks: {}", skipped))?;
+        }
+    }
     result
 }
 
-fn env_resolver(name: &str) -> Option<String> {
-    env::var(name).ok()
+// Remove compilation outputs: command tasks outputs are unknown.
+fn clean_graph(graph: &BuildGraph, console: &Console) -> Result<Option<i32>, Error> {
+    for node in graph.raw_nodes() {
+        if let BuildAction::Compilation(_, ref task) = node.weight.action {
+            let outputs = Some(&task.output_object)
+                .into_iter()
+                .chain(task.shared.output_precompiled.iter());
+            for output in outputs {
+                let path = task.shared.command.current_dir_join(output);
+                match fs::remove_file(&path) {
+                    Ok(_) => console.println(&format!("Removed: {}", path.display()))?,
+                    Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    Ok(Some(0))
+}
+
+fn task_failure(result: &BuildResult) -> Option<String> {
+    match result.result {
+        Ok(ref output) if output.success() => None,
+        Ok(ref output) => Some(match output.status {
+            Some(status) => format!("{} (exit code: {})", result.task.title, status),
+            None => format!("{} (terminated)", result.task.title),
+        }),
+        Err(ref e) => Some(format!("{} ({})", result.task.title, e)),
+    }
+}
+
+// Variables are resolved from task environment first, then from process environment.
+fn env_resolver<'a>(command: &'a CommandInfo) -> impl Fn(&str) -> Option<String> + 'a {
+    move |name: &str| {
+        command
+            .env
+            .get(name)
+            .map(|value| value.to_string())
+            .or_else(|| env::var(name).ok())
+    }
+}
+
+fn expand_path<F: Fn(&str) -> Option<String>>(path: &Path, resolver: &F) -> Result<PathBuf, Error> {
+    match path.to_str() {
+        Some(value) => expand_arg(value, resolver).map(PathBuf::from),
+        None => Ok(path.to_path_buf()),
+    }
 }
 
-fn prepare_graph<C: Compiler>(compiler: &C, graph: XgGraph) -> Result<BuildGraph, Error> {
+// Tasks with AllowRemote="False" are compiled by local compiler.
+// Tasks with AllowRestartOnLocal="False" aren't restarted locally after remote failure.
+// Reasons of command execution fallback are stored in `fallbacks` by node.
+fn prepare_graph<C: Compiler, R: Compiler, L: Compiler>(
+    console: &Console,
+    compiler: &C,
+    remote_only: &R,
+    local: &L,
+    graph: XgGraph,
+    fallbacks: &mut HashMap<NodeIndex, Error>,
+) -> Result<BuildGraph, Error> {
     let mut remap: Vec<NodeIndex> = Vec::with_capacity(graph.node_count());
     let mut depends: Vec<NodeIndex> = Vec::with_capacity(graph.node_count());
 
     let mut result: BuildGraph = Graph::new();
     for raw_node in graph.raw_nodes().iter() {
         let node: &XgNode = &raw_node.weight;
-        let args: Vec<String> = node
+        let resolver = env_resolver(&node.command);
+        let args = node
             .args
             .iter()
-            .map(|ref arg| expand_arg(&arg, &env_resolver))
-            .collect();
-        let command = node.command.clone();
+            .map(|arg| expand_arg(arg, &resolver))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let command = CommandInfo {
+            program: expand_path(&node.command.program, &resolver)?,
+            current_dir: match node.command.current_dir {
+                Some(ref dir) => Some(expand_path(dir, &resolver)?),
+                None => None,
+            },
+            env: node.command.env.clone(),
+        };
 
-        let actions = BuildAction::create_tasks(compiler, command.clone(), &args, &node.title);
+        let created = if !node.options.allow_remote {
+            BuildAction::try_create_tasks(local, command.clone(), &args)
+        } else if node.options.allow_restart_on_local {
+            BuildAction::try_create_tasks(compiler, command.clone(), &args)
+        } else {
+            BuildAction::try_create_tasks(remote_only, command.clone(), &args)
+        };
+        let actions = match created {
+            Ok(actions) => actions,
+            Err(e) => {
+                console.println(&format!(
+                    "Can't use octobuild for task {}: {}",
+                    node.title, e
+                ))?;
+                fallbacks.insert(NodeIndex::new(result.node_count()), e);
+                vec![BuildAction::Exec(command, args)]
+            }
+        };
         let node_index = NodeIndex::new(remap.len());
         if actions.len() == 1 {
             depends.push(node_index);
             remap.push(result.add_node(Arc::new(BuildTask {
                 title: node.title.clone(),
                 action: actions.into_iter().next().unwrap(),
+                options: node.options.clone(),
             })));
         } else {
             // Add group node for tracking end of all task actions
             let group_node = result.add_node(Arc::new(BuildTask {
                 title: node.title.clone(),
                 action: BuildAction::Empty,
+                options: node.options.clone(),
             }));
             depends.push(NodeIndex::end());
             // Add task actions
@@ -192,6 +489,7 @@ fn prepare_graph<C: Compiler>(compiler: &C, graph: XgGraph) -> Result<BuildGraph
                 let action_node = result.add_node(Arc::new(BuildTask {
                     title: format!("{} ({}/{})", node.title, index, total),
                     action,
+                    options: node.options.clone(),
                 }));
                 depends.push(node_index);
                 result.add_edge(group_node, action_node, ());
@@ -215,71 +513,321 @@ fn prepare_graph<C: Compiler>(compiler: &C, graph: XgGraph) -> Result<BuildGraph
     validate_graph(result)
 }
 
-fn print_task_result(result: BuildResult) -> Result<(), Error> {
-    println!(
+fn print_fallbacks(statistic: &FallbackStatistic, console: &Console) -> Result<(), Error> {
+    if statistic.is_empty() {
+        return Ok(());
+    }
+    console.write_out(statistic.to_string().as_bytes())
+}
+
+fn print_dry_run(
+    graph: &BuildGraph,
+    fallbacks: &HashMap<NodeIndex, Error>,
+    console: &Console,
+) -> Result<(), Error> {
+    for index in graph.node_indices() {
+        let task = &graph[index];
+        console.println(&format!("#{} {}", index.index(), task.title))?;
+        match task.action {
+            BuildAction::Empty => console.println("  group")?,
+            BuildAction::Exec(ref command, ref args) => {
+                console.println(&format!("  exec: {} {:?}", command.program.display(), args))?;
+                if let Some(reason) = fallbacks.get(&index) {
+                    console.println(&format!("  reason: {}", reason))?;
+                }
+            }
+            BuildAction::Compilation(ref toolchain, ref task) => {
+                console.println(&format!(
+                    "  compilation: {}",
+                    toolchain
+                        .identifier()
+                        .unwrap_or_else(|| "unknown toolchain".to_string())
+                ))?;
+                console.println(&format!("  language: {}", task.language))?;
+                console.println(&format!("  input: {}", task.input_source.display()))?;
+                console.println(&format!("  output: {}", task.output_object.display()))?;
+                console.println(&format!("  pch: {}", precompiled_role(task)))?;
+                for module in task.shared.input_modules.iter() {
+                    console.println(&format!("  module input: {}", module.to_arg()))?;
+                }
+                if let Some(ref path) = task.shared.output_module {
+                    console.println(&format!("  module output: {}", path.display()))?;
+                }
+            }
+        }
+        let depends: Vec<String> = graph
+            .neighbors_directed(index, EdgeDirection::Outgoing)
+            .map(|dep| format!("#{}", dep.index()))
+            .collect();
+        if !depends.is_empty() {
+            console.println(&format!("  depends: {}", depends.join(", ")))?;
+        }
+    }
+    Ok(())
+}
+
+fn precompiled_role(task: &CompilationTask) -> String {
+    let shared = &task.shared;
+    match (&shared.output_precompiled, &shared.input_precompiled) {
+        (Some(ref path), _) => format!("create {}", path.display()),
+        (None, Some(ref path)) => format!("use {}", path.display()),
+        (None, None) => "none".to_string(),
+    }
+}
+
+// Edge from task to its dependency.
+fn write_dot<W: Write>(
+    graph: &BuildGraph,
+    fallbacks: &HashMap<NodeIndex, Error>,
+    mut writer: W,
+) -> Result<(), Error> {
+    writeln!(writer, "digraph build {{")?;
+    writeln!(writer, "  node [shape=box];")?;
+    for index in graph.node_indices() {
+        let task = &graph[index];
+        let style = match task.action {
+            BuildAction::Empty => "shape=ellipse",
+            BuildAction::Exec(_, _) if fallbacks.contains_key(&index) => {
+                "style=filled, fillcolor=orange"
+            }
+            BuildAction::Exec(_, _) => "style=filled, fillcolor=lightgrey",
+            BuildAction::Compilation(_, _) => "style=filled, fillcolor=lightblue",
+        };
+        writeln!(
+            writer,
+            "  n{} [label=\"{}\", {}];",
+            index.index(),
+            escape_dot(&task.title),
+            style
+        )?;
+    }
+    for edge in graph.raw_edges() {
+        writeln!(
+            writer,
+            "  n{} -> n{};",
+            edge.source().index(),
+            edge.target().index()
+        )?;
+    }
+    writeln!(writer, "}}")
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn print_task_result(
+    options: &ConsoleOptions,
+    console: &Console,
+    result: BuildResult,
+) -> Result<(), Error> {
+    let mut line = format!(
         "#{} {}/{}: {}",
         result.worker, result.completed, result.total, result.task.title
     );
+    if options.show_agent {
+        if let Ok(ref output) = result.result {
+            let agent = output
+                .agent
+                .as_ref()
+                .map_or_else(|| output.origin.name(), |v| v.as_str());
+            line += &format!(" [{}]", agent);
+        }
+    }
+    if options.show_time {
+        line += &format!(" ({})", format_duration(result.duration));
+    }
+    console.println(&line)?;
     match result.result {
         Ok(ref output) => {
-            io::stdout().write_all(&output.stdout)?;
-            io::stderr().write_all(&output.stderr)?;
+            console.write_out(&output.stdout)?;
+            console.write_err(&output.stderr)?;
         }
         Err(_) => {}
     }
     Ok(())
 }
 
-fn expand_arg<F: Fn(&str) -> Option<String>>(arg: &str, resolver: &F) -> String {
+fn format_duration(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_millis())
+}
+
+// Expand $(NAME) references: variable values and names can contain references too.
+fn expand_arg<F: Fn(&str) -> Option<String>>(arg: &str, resolver: &F) -> Result<String, Error> {
+    expand_value(arg, resolver, &mut Vec::new())
+}
+
+fn expand_value<F: Fn(&str) -> Option<String>>(
+    value: &str,
+    resolver: &F,
+    stack: &mut Vec<String>,
+) -> Result<String, Error> {
     let mut result = String::new();
-    let mut suffix = arg;
-    loop {
-        match suffix.find("$(") {
-            Some(begin) => match suffix[begin..].find(')') {
-                Some(end) => {
-                    let name = &suffix[begin + 2..begin + end];
-                    match resolver(name) {
-                        Some(ref value) => {
-                            result += &suffix[..begin];
-                            result += &value;
-                        }
-                        None => {
-                            result += &suffix[..=begin + end];
-                        }
-                    }
-                    suffix = &suffix[begin + end + 1..];
-                }
-                None => {
-                    result += suffix;
-                    break;
+    let mut suffix = value;
+    while let Some(begin) = suffix.find("$(") {
+        let end = match find_reference_end(&suffix[begin + 2..]) {
+            Some(end) => begin + 2 + end,
+            None => break,
+        };
+        result += &suffix[..begin];
+        let name = expand_value(&suffix[begin + 2..end], resolver, stack)?;
+        match resolver(&name) {
+            Some(ref value) => {
+                if stack.contains(&name) {
+                    stack.push(name);
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Recursive variable reference: {}", stack.join(" -> ")),
+                    ));
                 }
-            },
+                stack.push(name);
+                result += &expand_value(value, resolver, stack)?;
+                stack.pop();
+            }
             None => {
-                result += suffix;
-                break;
+                result += "$(";
+                result += &name;
+                result += ")";
             }
         }
+        suffix = &suffix[end + 1..];
+    }
+    result += suffix;
+    Ok(result)
+}
+
+// Position of closing bracket with nested brackets support.
+fn find_reference_end(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(index),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+fn test_resolver(name: &str) -> Option<String> {
+    match name {
+        "test" => Some("foo".to_string()),
+        "inner" => Some("$(test)-$(bar)".to_string()),
+        "Platform" => Some("Linux".to_string()),
+        "Dir_Linux" => Some("/opt/$(test)".to_string()),
+        "loop" => Some("$(cycle)".to_string()),
+        "cycle" => Some("x$(loop)".to_string()),
+        "self" => Some("$(self)".to_string()),
+        _ => None,
     }
-    result
 }
 
 #[test]
 fn test_parse_vars() {
     assert_eq!(
-        expand_arg(
-            "A$(test)$(inner)$(none)B",
-            &|name: &str| -> Option<String> {
-                match name {
-                    "test" => Some("foo".to_string()),
-                    "inner" => Some("$(bar)".to_string()),
-                    "none" => None,
-                    _ => {
-                        unreachable!(format!("Unexpected value: {}", name));
-                    }
-                }
-            },
-        ),
-        "Afoo$(bar)$(none)B"
+        expand_arg("A$(test)$(inner)$(none)B", &test_resolver).unwrap(),
+        "Afoofoo-$(bar)$(none)B"
+    );
+    assert_eq!(
+        expand_arg("$(test)$(unclosed", &test_resolver).unwrap(),
+        "foo$(unclosed"
+    );
+}
+
+#[test]
+fn test_parse_vars_nested() {
+    assert_eq!(
+        expand_arg("$(Dir_$(Platform))/bin", &test_resolver).unwrap(),
+        "/opt/foo/bin"
+    );
+    assert_eq!(
+        expand_arg("$(Dir_$(missing))", &test_resolver).unwrap(),
+        "$(Dir_$(missing))"
+    );
+}
+
+#[test]
+fn test_parse_vars_cycle() {
+    assert!(expand_arg("$(loop)", &test_resolver).is_err());
+    assert!(expand_arg("$(self)", &test_resolver).is_err());
+    // Same variable used twice is not a cycle.
+    assert_eq!(
+        expand_arg("$(test)$(test)", &test_resolver).unwrap(),
+        "foofoo"
+    );
+}
+
+#[test]
+fn test_parse_options() {
+    let args: Vec<String> = [
+        "/Rebuild",
+        "/nologo",
+        "/ShowAgent",
+        "/ShowTime",
+        "/Out=build.log",
+        "/MaxCPUs=3",
+        "/Title=Game Build",
+        "/Wait",
+        "build.xml",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let options = ConsoleOptions::parse(&args).unwrap();
+    assert!(options.rebuild);
+    assert!(!options.clean);
+    assert!(options.no_logo);
+    assert!(options.show_agent);
+    assert!(options.show_time);
+    assert!(!options.keep_going);
+    assert_eq!(options.out, Some(PathBuf::from("build.log")));
+    assert_eq!(options.max_cpus, Some(3));
+    assert_eq!(options.title, Some("Game Build".to_string()));
+    assert_eq!(options.files, vec![PathBuf::from("build.xml")]);
+
+    assert!(ConsoleOptions::parse(&["/MaxCPUs=0".to_string()]).is_err());
+    assert!(ConsoleOptions::parse(&["/MaxCPUs=foo".to_string()]).is_err());
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_millis(12345)), "12.345s");
+    assert_eq!(format_duration(Duration::from_millis(7)), "0.007s");
+}
+
+#[test]
+fn test_write_dot() {
+    let mut graph: BuildGraph = Graph::new();
+    let group = graph.add_node(Arc::new(BuildTask {
+        title: "Link \"foo\"".to_string(),
+        action: BuildAction::Empty,
+        options: Default::default(),
+    }));
+    let exec = graph.add_node(Arc::new(BuildTask {
+        title: "Compile".to_string(),
+        action: BuildAction::Exec(CommandInfo::simple(Path::new("cc")), Vec::new()),
+        options: Default::default(),
+    }));
+    graph.add_edge(group, exec, ());
+    let mut fallbacks = HashMap::new();
+    fallbacks.insert(
+        exec,
+        Error::new(ErrorKind::InvalidInput, "unknown argument"),
+    );
+
+    let mut dot = Vec::new();
+    write_dot(&graph, &fallbacks, &mut dot).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        r#"digraph build {
+  node [shape=box];
+  n0 [label="Link \"foo\"", shape=ellipse];
+  n1 [label="Compile", style=filled, fillcolor=orange];
+  n0 -> n1;
+}
+"#
     );
 }
 
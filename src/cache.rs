@@ -5,7 +5,7 @@ use std::path::{Path, PathBuf};
 
 use super::compiler::OutputInfo;
 use super::config::Config;
-use super::io::filecache::FileCache;
+use super::io::filecache::{CachedOutput, FileCache};
 use super::io::memcache::MemCache;
 use super::io::statistic::Statistic;
 use super::utils::hash_stream;
@@ -39,7 +39,7 @@ impl Cache {
         &self,
         statistic: &Statistic,
         hash: &str,
-        outputs: &[PathBuf],
+        outputs: &[CachedOutput],
         worker: F,
         checker: C,
     ) -> Result<OutputInfo, Error> {
@@ -47,6 +47,10 @@ impl Cache {
             .run_cached(statistic, hash, outputs, worker, checker)
     }
 
+    pub fn set_rebuild(&mut self, rebuild: bool) {
+        self.file_cache.set_rebuild(rebuild)
+    }
+
     pub fn cleanup(&self) -> Result<(), Error> {
         self.file_cache.cleanup()
     }
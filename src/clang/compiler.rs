@@ -1,6 +1,7 @@
 use std::env;
+use std::fs;
 use std::io;
-use std::io::{Error, Read};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::mpsc::{channel, Receiver};
@@ -18,8 +19,17 @@ use super::super::lazy::Lazy;
 lazy_static! {
     static ref RE_CLANG: regex::bytes::Regex =
         regex::bytes::Regex::new(r"(?i)^(clang(:?\+\+)?)(-\d+\.\d+)?(?:.exe)?$").unwrap();
+    // System libraries must be taken from builder host.
+    static ref RE_SYSTEM_LIBRARY: Regex =
+        Regex::new(r"^(ld-linux.*|linux-vdso.*|lib(c|m|dl|rt|pthread|resolv|util)\.so(\..*)?)$")
+            .unwrap();
 }
 
+// Toolchain package layout.
+const PACKAGE_BIN: &str = "bin";
+const PACKAGE_RESOURCE: &str = "resource";
+const PACKAGE_LIB: &str = "lib";
+
 #[derive(Default)]
 pub struct ClangCompiler {
     toolchains: ToolchainHolder,
@@ -34,6 +44,9 @@ impl ClangCompiler {
 struct ClangToolchain {
     path: PathBuf,
     identifier: Lazy<Option<String>>,
+    // Overrides for toolchain unpacked from package.
+    resource_dir: Option<PathBuf>,
+    library_dir: Option<PathBuf>,
 }
 
 impl ClangToolchain {
@@ -41,6 +54,19 @@ impl ClangToolchain {
         ClangToolchain {
             path,
             identifier: Default::default(),
+            resource_dir: None,
+            library_dir: None,
+        }
+    }
+
+    fn packaged(path: PathBuf, identifier: &str, root: &Path) -> Self {
+        let resource_dir = root.join(PACKAGE_RESOURCE);
+        let library_dir = root.join(PACKAGE_LIB);
+        ClangToolchain {
+            path,
+            identifier: Lazy::with_value(Some(identifier.to_string())),
+            resource_dir: Some(resource_dir).filter(|p| p.is_dir()),
+            library_dir: Some(library_dir).filter(|p| p.is_dir()),
         }
     }
 }
@@ -70,6 +96,20 @@ impl Compiler for ClangCompiler {
             .map(|entry| -> Arc<dyn Toolchain> { Arc::new(ClangToolchain::new(entry.path())) })
             .collect()
     }
+
+    fn load_package(&self, identifier: &str, root: &Path) -> Option<Arc<dyn Toolchain>> {
+        let entry = root
+            .join(PACKAGE_BIN)
+            .read_dir()
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| RE_CLANG.is_match(entry.file_name().to_string_lossy().as_bytes()))?;
+        Some(Arc::new(ClangToolchain::packaged(
+            entry.path(),
+            identifier,
+            root,
+        )))
+    }
 }
 
 impl Toolchain for ClangToolchain {
@@ -81,7 +121,7 @@ impl Toolchain for ClangToolchain {
         &self,
         command: CommandInfo,
         args: &[String],
-    ) -> Result<Vec<CompilationTask>, String> {
+    ) -> Result<Vec<CompilationTask>, CompilerError> {
         super::prepare::create_tasks(command, args)
     }
 
@@ -90,45 +130,7 @@ impl Toolchain for ClangToolchain {
         state: &SharedState,
         task: &CompilationTask,
     ) -> Result<PreprocessResult, Error> {
-        let mut args = Vec::new();
-        args.push("-E".to_string());
-        args.push("-x".to_string());
-        args.push(task.language.clone());
-        args.push("-frewrite-includes".to_string());
-
-        // Make parameters list for preprocessing.
-        for arg in task.shared.args.iter() {
-            match arg {
-                Arg::Flag {
-                    ref scope,
-                    ref flag,
-                } => match scope {
-                    Scope::Preprocessor | &Scope::Shared => {
-                        args.push("-".to_string() + &flag);
-                    }
-                    Scope::Ignore | &Scope::Compiler => {}
-                },
-                Arg::Param {
-                    ref scope,
-                    ref flag,
-                    ref value,
-                } => match scope {
-                    Scope::Preprocessor | &Scope::Shared => {
-                        args.push("-".to_string() + &flag);
-                        args.push(value.clone());
-                    }
-                    Scope::Ignore | &Scope::Compiler => {}
-                },
-                Arg::Input { .. } => {}
-                Arg::Output { .. } => {}
-            };
-        }
-
-        // Add preprocessor paramters.
-        args.push(task.input_source.display().to_string());
-        args.push("-o".to_string());
-        args.push("-".to_string());
-
+        let args = preprocess_arguments(task);
         state.wrap_slow(|| execute(task.shared.command.to_command().args(&args)))
     }
 
@@ -167,21 +169,49 @@ impl Toolchain for ClangToolchain {
                 Arg::Output { .. } => {}
             };
         }
-        Ok(CompileStep::new(task, preprocessed, args, false))
+        let precompile = super::prepare::is_precompile(&task.shared.args);
+        let mut step = CompileStep::new(task, preprocessed, args, true);
+        if step.output_precompiled.is_some() || precompile {
+            // Precompiled header or module interface is written instead of object file.
+            step.output_object = None;
+        }
+        Ok(step)
     }
 
     fn compile_step(&self, state: &SharedState, task: CompileStep) -> Result<OutputInfo, Error> {
         // Run compiler.
         state.wrap_slow(|| {
-            let mut child = Command::new(&self.path)
-                .env_clear()
-                .arg("-c")
-                .args(&task.args)
+            let mut command = Command::new(&self.path);
+            command.env_clear();
+            if let Some(ref library_dir) = self.library_dir {
+                command.env("LD_LIBRARY_PATH", library_dir);
+            }
+            if let Some(ref resource_dir) = self.resource_dir {
+                command.arg("-resource-dir").arg(resource_dir);
+            }
+            if !task.args.iter().any(|arg| arg == "--precompile") {
+                command.arg("-c");
+            }
+            command.args(&task.args);
+            // Use precompiled header
+            if let Some(ref path) = task.input_precompiled {
+                command.arg("-include-pch").arg(path);
+            }
+            // Compiled module interfaces.
+            for module in task.input_modules.iter() {
+                command.arg(format!("-fmodule-file={}", module.to_arg()));
+            }
+            if let (Some(ref path), Some(_)) = (&task.output_module, &task.output_object) {
+                command.arg(format!("-fmodule-output={}", path.display()));
+            }
+            let mut child = command
                 .arg("-")
                 .arg("-o")
                 .arg(
-                    task.output_object
+                    task.output_precompiled
                         .as_ref()
+                        .or(task.output_object.as_ref())
+                        .or(task.output_module.as_ref())
                         .map_or("-".to_string(), |path| path.display().to_string()),
                 )
                 .stdin(Stdio::piped())
@@ -194,6 +224,155 @@ impl Toolchain for ClangToolchain {
             Ok(OutputInfo::new(output))
         })
     }
+
+    // Package compiler binary with resource directory and non-system shared libraries.
+    fn create_package(&self, output: &mut dyn Write) -> Result<bool, Error> {
+        let binary = fs::canonicalize(&self.path)?;
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid compiler path"))?;
+        let mut archive = tar::Builder::new(output);
+        archive.append_path_with_name(&binary, Path::new(PACKAGE_BIN).join(name))?;
+        if let Some(resource_dir) = clang_resource_dir(&self.path) {
+            archive.append_dir_all(PACKAGE_RESOURCE, resource_dir)?;
+        }
+        for library in shared_libraries(&binary)? {
+            if let Some(name) = library.file_name() {
+                archive.append_path_with_name(&library, Path::new(PACKAGE_LIB).join(name))?;
+            }
+        }
+        archive.finish()?;
+        Ok(true)
+    }
+
+    fn package_binary(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+}
+
+fn preprocess_arguments(task: &CompilationTask) -> Vec<String> {
+    let mut args = vec![
+        "-E".to_string(),
+        "-x".to_string(),
+        task.language.clone(),
+        "-frewrite-includes".to_string(),
+    ];
+
+    // Make parameters list for preprocessing.
+    for arg in task.shared.args.iter() {
+        match arg {
+            Arg::Flag {
+                ref scope,
+                ref flag,
+            } => match scope {
+                Scope::Preprocessor | &Scope::Shared => {
+                    args.push("-".to_string() + flag);
+                }
+                Scope::Ignore | &Scope::Compiler => {}
+            },
+            Arg::Param {
+                ref scope,
+                ref flag,
+                ref value,
+            } => match scope {
+                Scope::Preprocessor | &Scope::Shared => {
+                    args.push("-".to_string() + flag);
+                    args.push(value.clone());
+                }
+                Scope::Ignore | &Scope::Compiler => {}
+            },
+            Arg::Input {
+                ref kind, ref file, ..
+            } if *kind == InputKind::Precompiled => {
+                args.push("-include-pch".to_string());
+                args.push(file.clone());
+            }
+            Arg::Input { .. } => {}
+            Arg::Output { .. } => {}
+        };
+    }
+
+    // Preprocessor output goes to stdout: dependency file needs explicit target and path.
+    if has_dependency_file(&task.shared.args) {
+        if find_param(&task.shared.args, &["MT", "MQ"]).is_none() {
+            args.push("-MT".to_string());
+            args.push(task.output_object.display().to_string());
+        }
+        if find_param(&task.shared.args, &["MF"]).is_none() {
+            args.push("-MF".to_string());
+            args.push(task.output_object.with_extension("d").display().to_string());
+        }
+    }
+
+    // Add preprocessor paramters.
+    args.push(task.input_source.display().to_string());
+    args.push("-o".to_string());
+    args.push("-".to_string());
+    args
+}
+
+fn has_dependency_file(args: &[Arg]) -> bool {
+    args.iter().any(|arg| match arg {
+        Arg::Flag { ref flag, .. } => flag == "MD" || flag == "MMD",
+        _ => false,
+    })
+}
+
+fn find_param<'a>(args: &'a [Arg], names: &[&str]) -> Option<&'a str> {
+    args.iter().find_map(|arg| match arg {
+        Arg::Param {
+            ref flag,
+            ref value,
+            ..
+        } if names.contains(&flag.as_str()) => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+fn clang_resource_dir(clang: &Path) -> Option<PathBuf> {
+    let output = Command::new(clang.as_os_str())
+        .arg("-print-resource-dir")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+    .filter(|path| path.is_dir())
+}
+
+#[cfg(target_os = "linux")]
+fn shared_libraries(binary: &Path) -> Result<Vec<PathBuf>, Error> {
+    let output = Command::new("ldd").arg(binary).output()?;
+    if !output.status.success() {
+        // Statically linked binary
+        return Ok(Vec::new());
+    }
+    Ok(parse_ldd(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn shared_libraries(_binary: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(Vec::new())
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_ldd(stdout: &str) -> Vec<PathBuf> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, " => ");
+            let name = parts.next()?;
+            let path = parts.next()?.split(" (").next()?.trim();
+            if !path.starts_with('/') || RE_SYSTEM_LIBRARY.is_match(name) {
+                return None;
+            }
+            Some(PathBuf::from(path))
+        })
+        .collect()
 }
 
 fn clang_parse_version(base_name: &str, stdout: &str) -> Option<String> {
@@ -277,12 +456,87 @@ fn execute(command: &mut Command) -> Result<PreprocessResult, Error> {
             status: status.code(),
             stdout: Vec::new(),
             stderr,
+            origin: OutputOrigin::Local,
+            agent: None,
         }))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::path::PathBuf;
+
+    use crate::compiler::test::{split_args, test_command};
+
+    use super::super::prepare::create_tasks;
+
+    fn preprocess_arguments(line: &str) -> Vec<String> {
+        let tasks =
+            create_tasks(test_command("clang++", Some("/build")), &split_args(line)).unwrap();
+        super::preprocess_arguments(&tasks[0])
+    }
+
+    #[test]
+    fn test_preprocess_dependency_file() {
+        assert_eq!(
+            preprocess_arguments("-c -MD -o Core.o Core.cpp"),
+            vec![
+                "-E",
+                "-x",
+                "c++",
+                "-frewrite-includes",
+                "-MD",
+                "-MT",
+                "Core.o",
+                "-MF",
+                "Core.d",
+                "Core.cpp",
+                "-o",
+                "-"
+            ]
+        );
+        assert_eq!(
+            preprocess_arguments("-c -MMD -MT Core.obj -MF deps/Core.dep -o Core.o Core.cpp"),
+            vec![
+                "-E",
+                "-x",
+                "c++",
+                "-frewrite-includes",
+                "-MMD",
+                "-MT",
+                "Core.obj",
+                "-MF",
+                "deps/Core.dep",
+                "Core.cpp",
+                "-o",
+                "-"
+            ]
+        );
+        assert!(!preprocess_arguments("-c -o Core.o Core.cpp").contains(&"-MT".to_string()));
+    }
+
+    #[test]
+    fn test_parse_ldd() {
+        assert_eq!(
+            super::parse_ldd(
+                r#"	linux-vdso.so.1 (0x00007ffd8c5f2000)
+	libclang-cpp.so.14 => /lib/x86_64-linux-gnu/libclang-cpp.so.14 (0x00007f0d6c000000)
+	libLLVM-14.so.1 => /lib/x86_64-linux-gnu/libLLVM-14.so.1 (0x00007f0d65a00000)
+	libstdc++.so.6 => /lib/x86_64-linux-gnu/libstdc++.so.6 (0x00007f0d657d4000)
+	libm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f0d6ff19000)
+	libc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f0d655ac000)
+	libmissing.so.1 => not found
+	/lib64/ld-linux-x86-64.so.2 (0x00007f0d70010000)
+"#
+            ),
+            vec![
+                PathBuf::from("/lib/x86_64-linux-gnu/libclang-cpp.so.14"),
+                PathBuf::from("/lib/x86_64-linux-gnu/libLLVM-14.so.1"),
+                PathBuf::from("/lib/x86_64-linux-gnu/libstdc++.so.6"),
+            ]
+        )
+    }
+
     #[test]
     fn test_ubuntu_14_04_clang_3_5() {
         assert_eq!(
@@ -1,32 +1,37 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 use std::slice::Iter;
 use std::sync::Arc;
 
+use crate::cmd;
 use crate::compiler::{
-    Arg, CommandInfo, CompilationArgs, CompilationTask, InputKind, OutputKind, Scope,
+    Arg, CommandInfo, CompilationArgs, CompilationTask, CompilerError, InputKind, ModuleFile,
+    OutputKind, Scope,
 };
 
+// Maximum response file nesting (protection from recursive response files).
+const RESPONSE_FILE_DEPTH: usize = 16;
+
 enum ParamValue<T> {
     None,
     Single(T),
     Many(Vec<T>),
 }
 
-pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<CompilationTask>, String> {
+pub fn create_tasks(
+    command: CommandInfo,
+    args: &[String],
+) -> Result<Vec<CompilationTask>, CompilerError> {
+    let args = &load_arguments(&command.current_dir, args)
+        .map_err(|e| CompilerError::InvalidArguments(format!("IO error: {}", e)))?;
     if args.iter().any(|v| v == "--analyze") {
         // Support only compilation steps
         return Ok(Vec::new());
     }
-    if args
-        .iter()
-        .find(|v| match v as &str {
-            "-c" => true,
-            _ => false,
-        })
-        .is_none()
-    {
-        // Support only compilation steps
+    if !args.iter().any(|v| v == "-c" || v == "--precompile") && !has_precompiled_language(args) {
+        // Support only compilation, precompiled header and module interface generation steps
         return Ok(Vec::new());
     }
     let parsed_args = parse_arguments(args)?;
@@ -41,7 +46,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
         })
         .collect();
     if input_sources.is_empty() {
-        return Err("Can't find source file path.".to_string());
+        return Err("Can't find source file path.".to_string().into());
     }
     // Precompiled header file name.
     let input_precompiled = match find_param(&parsed_args, |arg: &Arg| -> Option<PathBuf> {
@@ -55,7 +60,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
         ParamValue::None => None,
         ParamValue::Single(v) => Some(v),
         ParamValue::Many(v) => {
-            return Err(format!("Found too many precompiled header files: {:?}", v));
+            return Err(format!("Found too many precompiled header files: {:?}", v).into());
         }
     };
     // Precompiled header file name.
@@ -82,12 +87,14 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
         ParamValue::None => None,
         ParamValue::Single(v) => {
             if input_sources.len() > 1 {
-                return Err("Cannot specify -o when generating multiple output files".to_string());
+                return Err("Cannot specify -o when generating multiple output files"
+                    .to_string()
+                    .into());
             }
             Some(v)
         }
         ParamValue::Many(v) => {
-            return Err(format!("Found too many output object files: {:?}", v));
+            return Err(format!("Found too many output object files: {:?}", v).into());
         }
     };
     // Language
@@ -102,64 +109,204 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
         }
     }) {
         ParamValue::None => None,
-        ParamValue::Single(v) => {
-            match &v[..] {
-                "c" | "c++" => Some(v.to_string()),
-                "c-header" | "c++-header" => {
-                    // Precompiled headers must build locally
-                    return Ok(Vec::new());
-                }
-                _ => {
-                    return Err(format!("Unknown source language type: {}", v));
-                }
+        ParamValue::Single(v) => match &v[..] {
+            "c" | "c++" | "c-header" | "c++-header" | "c++-module" => Some(v.to_string()),
+            _ => {
+                return Err(format!("Unknown source language type: {}", v).into());
             }
-        }
+        },
         ParamValue::Many(v) => {
-            return Err(format!("Found too many output object files: {:?}", v));
+            return Err(format!("Found too many output object files: {:?}", v).into());
+        }
+    };
+    let sources = input_sources
+        .into_iter()
+        .map(|source| {
+            language
+                .clone()
+                .or_else(|| {
+                    source
+                        .extension()
+                        .and_then(|ext| match ext.to_str() {
+                            Some(e) if e.eq_ignore_ascii_case("cpp") => Some("c++"),
+                            Some(e) if e.eq_ignore_ascii_case("c") => Some("c"),
+                            Some(e) if e.eq_ignore_ascii_case("hpp") => Some("c++-header"),
+                            Some(e) if e.eq_ignore_ascii_case("h") => Some("c-header"),
+                            Some(e) if e.eq_ignore_ascii_case("cppm") => Some("c++-module"),
+                            _ => None,
+                        })
+                        .map(|ext| ext.to_string())
+                })
+                .map(|language| (source.clone(), language))
+                .ok_or_else(|| {
+                    format!(
+                        "Can't detect file language by extension: {}",
+                        source.as_os_str().to_string_lossy()
+                    )
+                })
+        })
+        .collect::<Result<Vec<(PathBuf, String)>, String>>()?;
+    // Precompiled header is the only output of header compilation.
+    let output_precompiled = match sources
+        .iter()
+        .find(|(_, language)| is_precompiled_language(language))
+    {
+        Some((source, _)) => {
+            if sources.len() > 1 {
+                return Err(
+                    "Cannot generate precompiled header with multiple input files"
+                        .to_string()
+                        .into(),
+                );
+            }
+            Some(output_object.clone().unwrap_or_else(|| {
+                let mut path = source.clone().into_os_string();
+                path.push(".gch");
+                PathBuf::from(path)
+            }))
+        }
+        None => None,
+    };
+    // Compiled module interfaces.
+    let input_modules: Vec<ModuleFile> = parsed_args
+        .iter()
+        .filter_map(|arg| match arg {
+            Arg::Input {
+                ref kind, ref file, ..
+            } if *kind == InputKind::Module => Some(ModuleFile::parse(file)),
+            _ => None,
+        })
+        .map(|module| ModuleFile {
+            path: command.current_dir_join(&module.path),
+            ..module
+        })
+        .collect();
+    // Compiled module interface is the only output of --precompile.
+    let precompile = is_precompile(&parsed_args);
+    let output_module = if precompile {
+        if sources.len() > 1 {
+            return Err("Cannot precompile multiple module interface units at once"
+                .to_string()
+                .into());
+        }
+        Some(
+            output_object
+                .clone()
+                .unwrap_or_else(|| sources[0].0.with_extension("pcm")),
+        )
+    } else {
+        match find_param(&parsed_args, |arg: &Arg| -> Option<PathBuf> {
+            match arg {
+                Arg::Output {
+                    ref kind, ref file, ..
+                } if *kind == OutputKind::Module => Some(Path::new(file).to_path_buf()),
+                _ => None,
+            }
+        }) {
+            ParamValue::None => None,
+            ParamValue::Single(v) => {
+                if sources.len() > 1 {
+                    return Err("Cannot specify -fmodule-output with multiple input files"
+                        .to_string()
+                        .into());
+                }
+                Some(v)
+            }
+            ParamValue::Many(v) => {
+                return Err(format!("Found too many module output files: {:?}", v).into());
+            }
         }
     };
     let shared = Arc::new(CompilationArgs {
-        command,
+        input_precompiled: input_precompiled.map(|path| command.current_dir_join(&path)),
+        input_modules,
+        output_module: output_module
+            .as_ref()
+            .map(|path| command.current_dir_join(path)),
+        output_precompiled: output_precompiled
+            .as_ref()
+            .map(|path| command.current_dir_join(path)),
         args: parsed_args,
-        output_precompiled: None,
         marker_precompiled,
-        input_precompiled,
+        parallel_limit: None,
+        command,
     });
-    input_sources
+    Ok(sources
         .into_iter()
-        .map(|source| {
-            Ok(CompilationTask {
-                shared: shared.clone(),
-                language: language
-                    .as_ref()
-                    .map_or_else(
-                        || {
-                            source
-                                .extension()
-                                .and_then(|ext| match ext.to_str() {
-                                    Some(e) if e.eq_ignore_ascii_case("cpp") => Some("c++"),
-                                    Some(e) if e.eq_ignore_ascii_case("c") => Some("c"),
-                                    Some(e) if e.eq_ignore_ascii_case("hpp") => Some("c++-header"),
-                                    Some(e) if e.eq_ignore_ascii_case("h") => Some("c-header"),
-                                    _ => None,
-                                })
-                                .map(|ext| ext.to_string())
-                        },
-                        |lang| Some(lang.clone()),
-                    )
-                    .ok_or_else(|| {
-                        format!(
-                            "Can't detect file language by extension: {}",
-                            source.as_os_str().to_string_lossy()
-                        )
-                    })?,
-                output_object: output_object
-                    .as_ref()
-                    .map_or_else(|| source.with_extension("o"), |path| path.clone()),
-                input_source: source,
-            })
+        .map(|(source, language)| CompilationTask {
+            shared: shared.clone(),
+            language,
+            output_object: output_precompiled
+                .clone()
+                .or_else(|| output_module.clone().filter(|_| precompile))
+                .or_else(|| output_object.clone())
+                .unwrap_or_else(|| source.with_extension("o")),
+            input_source: source,
         })
-        .collect()
+        .collect())
+}
+
+// Module interface precompilation (--precompile).
+pub fn is_precompile(args: &[Arg]) -> bool {
+    args.iter()
+        .any(|arg| *arg == Arg::flag(Scope::Compiler, "-precompile"))
+}
+
+fn is_precompiled_language(language: &str) -> bool {
+    language == "c-header" || language == "c++-header"
+}
+
+// Header compilation with explicit language (-x c++-header) doesn't require -c flag.
+fn has_precompiled_language(args: &[String]) -> bool {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let language = match &arg[..] {
+            "-x" => iter.next().map(|v| &v[..]),
+            _ if arg.starts_with("-x") => Some(&arg[2..]),
+            _ => None,
+        };
+        if let Some(true) = language.map(is_precompiled_language) {
+            return true;
+        }
+    }
+    false
+}
+
+// Expand response files (@file) with GNU quoting rules.
+fn load_arguments(base: &Option<PathBuf>, args: &[String]) -> Result<Vec<String>, Error> {
+    let mut result = Vec::new();
+    expand_arguments(base, args, &mut result, 0)?;
+    Ok(result)
+}
+
+fn expand_arguments(
+    base: &Option<PathBuf>,
+    args: &[String],
+    result: &mut Vec<String>,
+    depth: usize,
+) -> Result<(), Error> {
+    for arg in args.iter() {
+        if arg.len() > 1 && arg.starts_with('@') {
+            if depth >= RESPONSE_FILE_DEPTH {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Too deep response file nesting: {}", arg),
+                ));
+            }
+            let path = match base {
+                Some(ref p) => p.join(&arg[1..]),
+                None => Path::new(&arg[1..]).to_path_buf(),
+            };
+            let text = String::from_utf8(fs::read(&path)?)
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+            // Nested response files are relative to containing file.
+            let nested_base = path.parent().map(Path::to_path_buf);
+            expand_arguments(&nested_base, &cmd::unix::parse(&text)?, result, depth + 1)?;
+        } else {
+            result.push(arg.clone());
+        }
+    }
+    Ok(())
 }
 
 fn find_param<T, R, F: Fn(&T) -> Option<R>>(args: &[T], filter: F) -> ParamValue<R> {
@@ -171,7 +318,7 @@ fn find_param<T, R, F: Fn(&T) -> Option<R>>(args: &[T], filter: F) -> ParamValue
     }
 }
 
-fn parse_arguments(args: &[String]) -> Result<Vec<Arg>, String> {
+fn parse_arguments(args: &[String]) -> Result<Vec<Arg>, CompilerError> {
     let mut result: Vec<Arg> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
     let mut iter = args.iter();
@@ -186,17 +333,16 @@ fn parse_arguments(args: &[String]) -> Result<Vec<Arg>, String> {
         }
     }
     if !errors.is_empty() {
-        return Err(format!(
-            "Found unknown command line arguments: {:?}",
-            errors
-        ));
+        return Err(CompilerError::UnknownArguments(errors));
     }
     Ok(result)
 }
 
 fn parse_argument(iter: &mut Iter<String>) -> Option<Result<Arg, String>> {
     match iter.next() {
-        Some(arg) => Some(if arg.starts_with("--") {
+        Some(arg) => Some(if arg == "--precompile" {
+            Ok(Arg::flag(Scope::Compiler, &arg[1..]))
+        } else if arg.starts_with("--") {
             let (key, value) = match arg.find('=') {
                 Some(position) => (&arg[1..position], arg[position + 1..].to_string()),
                 None => match iter.next() {
@@ -207,11 +353,20 @@ fn parse_argument(iter: &mut Iter<String>) -> Option<Result<Arg, String>> {
                 },
             };
             match &key[1..] {
-                "sysroot" => Ok(Arg::flag(Scope::Shared, key.to_string() + "=" + &value)),
+                "sysroot" | "gcc-toolchain" | "target" => {
+                    Ok(Arg::flag(Scope::Shared, key.to_string() + "=" + &value))
+                }
                 _ => Err(key.to_string()),
             }
         } else if has_param_prefix(arg) {
             let flag = &arg[1..];
+            if let Some(scope) = is_forward_param(flag) {
+                // Value is passed as is: it can look like flag.
+                return Some(match iter.next() {
+                    Some(v) => Ok(Arg::param(scope, flag, v.to_string())),
+                    None => Err(arg.to_string()),
+                });
+            }
             match is_spaceable_param(flag) {
                 Some((prefix, scope, next_flag)) => {
                     let value = if flag == prefix {
@@ -224,21 +379,41 @@ fn parse_argument(iter: &mut Iter<String>) -> Option<Result<Arg, String>> {
                     } else {
                         flag[prefix.len()..].to_string()
                     };
-                    match flag {
+                    match prefix {
                         "o" => Ok(Arg::output(OutputKind::Object, prefix, value)),
+                        "include-pch" => Ok(Arg::input(InputKind::Precompiled, prefix, value)),
                         _ => Ok(Arg::param(scope, prefix, value)),
                     }
                 }
                 None => match flag {
                     "c" => Ok(Arg::flag(Scope::Ignore, flag)),
                     "pipe" => Ok(Arg::flag(Scope::Shared, flag)),
-                    "nostdinc++" => Ok(Arg::flag(Scope::Shared, flag)),
+                    "nostdinc" | "nostdinc++" | "nostdlibinc" => Ok(Arg::flag(Scope::Shared, flag)),
+                    "pthread" | "pedantic" | "pedantic-errors" | "w" | "ansi" | "undef" => {
+                        Ok(Arg::flag(Scope::Shared, flag))
+                    }
+                    // Dependency file is written by preprocessor.
+                    "MD" | "MMD" | "MP" => Ok(Arg::flag(Scope::Preprocessor, flag)),
+                    s if s.starts_with("fmodule-file=") => Ok(Arg::input(
+                        InputKind::Module,
+                        "fmodule-file=",
+                        &s["fmodule-file=".len()..],
+                    )),
+                    s if s.starts_with("fmodule-output=") => Ok(Arg::output(
+                        OutputKind::Module,
+                        "fmodule-output=",
+                        &s["fmodule-output=".len()..],
+                    )),
+                    // Module files are searched by name: result can't be cached.
+                    "fmodule-output" => Err(arg.to_string()),
+                    s if s.starts_with("fprebuilt-module-path") => Err(arg.to_string()),
                     s if s.starts_with('f') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with('g') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with('O') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with('W') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with('m') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with("std=") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("stdlib=") => Ok(Arg::flag(Scope::Shared, flag)),
                     _ => Err(arg.to_string()),
                 },
             }
@@ -253,12 +428,23 @@ fn parse_argument(iter: &mut Iter<String>) -> Option<Result<Arg, String>> {
     }
 }
 
+// Parameters with value in next argument without any restrictions.
+fn is_forward_param(flag: &str) -> Option<Scope> {
+    match flag {
+        "Xclang" => Some(Scope::Shared),
+        "Xpreprocessor" => Some(Scope::Preprocessor),
+        "mllvm" => Some(Scope::Compiler),
+        _ => None,
+    }
+}
+
 fn is_spaceable_param(flag: &str) -> Option<(&str, Scope, bool)> {
     match flag {
         "include" | "include-pch" => Some((flag, Scope::Preprocessor, false)),
-        "target" => Some((flag, Scope::Shared, false)),
+        "target" | "arch" | "gcc-toolchain" | "resource-dir" => Some((flag, Scope::Shared, false)),
         _ => {
-            for prefix in ["D", "o"].iter() {
+            // Macros are still used on compilation of preprocessed by -frewrite-includes file.
+            for prefix in ["D", "U", "o", "isysroot"].iter() {
                 if flag.starts_with(*prefix) {
                     return Some((*prefix, Scope::Shared, false));
                 }
@@ -268,7 +454,20 @@ fn is_spaceable_param(flag: &str) -> Option<(&str, Scope, bool)> {
                     return Some((*prefix, Scope::Ignore, false));
                 }
             }
-            for prefix in ["I"].iter() {
+            for prefix in [
+                "I",
+                "isystem",
+                "iquote",
+                "idirafter",
+                "iprefix",
+                "iwithprefixbefore",
+                "iwithprefix",
+                "MF",
+                "MT",
+                "MQ",
+            ]
+            .iter()
+            {
                 if flag.starts_with(*prefix) {
                     return Some((*prefix, Scope::Preprocessor, false));
                 }
@@ -337,7 +536,11 @@ fn test_parse_argument_compile() {
         parse_arguments(&args).unwrap(),
         [
             Arg::flag(Scope::Ignore, "c"),
-            Arg::param(Scope::Preprocessor, "include-pch", "CorePrivatePCH.h.pch"),
+            Arg::input(
+                InputKind::Precompiled,
+                "include-pch",
+                "CorePrivatePCH.h.pch"
+            ),
             Arg::flag(Scope::Shared, "pipe"),
             Arg::flag(Scope::Shared, "Wall"),
             Arg::flag(Scope::Shared, "Werror"),
@@ -362,3 +565,280 @@ fn test_parse_argument_compile() {
         ]
     )
 }
+
+#[test]
+fn test_parse_argument_table() {
+    let cases: Vec<(&str, Vec<Arg>)> = vec![
+        (
+            "-isystem ThirdParty/Include -isystemThirdParty/Other",
+            vec![
+                Arg::param(Scope::Preprocessor, "isystem", "ThirdParty/Include"),
+                Arg::param(Scope::Preprocessor, "isystem", "ThirdParty/Other"),
+            ],
+        ),
+        (
+            "-iquote Source -idirafter /opt/include",
+            vec![
+                Arg::param(Scope::Preprocessor, "iquote", "Source"),
+                Arg::param(Scope::Preprocessor, "idirafter", "/opt/include"),
+            ],
+        ),
+        (
+            "-U NDEBUG -U_DEBUG",
+            vec![
+                Arg::param(Scope::Shared, "U", "NDEBUG"),
+                Arg::param(Scope::Shared, "U", "_DEBUG"),
+            ],
+        ),
+        (
+            "-Xclang -fno-pch-timestamp -Xclang -load -Xclang plugin.so",
+            vec![
+                Arg::param(Scope::Shared, "Xclang", "-fno-pch-timestamp"),
+                Arg::param(Scope::Shared, "Xclang", "-load"),
+                Arg::param(Scope::Shared, "Xclang", "plugin.so"),
+            ],
+        ),
+        (
+            "-mllvm -inline-threshold=100",
+            vec![Arg::param(
+                Scope::Compiler,
+                "mllvm",
+                "-inline-threshold=100",
+            )],
+        ),
+        (
+            "-stdlib=libc++ -nostdinc -nostdinc++ -nostdlibinc",
+            vec![
+                Arg::flag(Scope::Shared, "stdlib=libc++"),
+                Arg::flag(Scope::Shared, "nostdinc"),
+                Arg::flag(Scope::Shared, "nostdinc++"),
+                Arg::flag(Scope::Shared, "nostdlibinc"),
+            ],
+        ),
+        (
+            "-isysroot /sdk --sysroot=/sysroot --gcc-toolchain=/gcc -gcc-toolchain /gcc2",
+            vec![
+                Arg::param(Scope::Shared, "isysroot", "/sdk"),
+                Arg::flag(Scope::Shared, "-sysroot=/sysroot"),
+                Arg::flag(Scope::Shared, "-gcc-toolchain=/gcc"),
+                Arg::param(Scope::Shared, "gcc-toolchain", "/gcc2"),
+            ],
+        ),
+        (
+            "-arch x86_64 -target x86_64-unknown-linux-gnu --target=aarch64-unknown-linux-gnueabi",
+            vec![
+                Arg::param(Scope::Shared, "arch", "x86_64"),
+                Arg::param(Scope::Shared, "target", "x86_64-unknown-linux-gnu"),
+                Arg::flag(Scope::Shared, "-target=aarch64-unknown-linux-gnueabi"),
+            ],
+        ),
+        // Coverage notes are cached as optional output.
+        (
+            "-ftest-coverage",
+            vec![Arg::flag(Scope::Shared, "ftest-coverage")],
+        ),
+        (
+            "-MD -MF Module.Core.cpp.d -MT Module.Core.cpp.o -MP -MMD",
+            vec![
+                Arg::flag(Scope::Preprocessor, "MD"),
+                Arg::param(Scope::Preprocessor, "MF", "Module.Core.cpp.d"),
+                Arg::param(Scope::Preprocessor, "MT", "Module.Core.cpp.o"),
+                Arg::flag(Scope::Preprocessor, "MP"),
+                Arg::flag(Scope::Preprocessor, "MMD"),
+            ],
+        ),
+        (
+            "-Werror=return-type -fsanitize=address,undefined -fno-sanitize-recover=all",
+            vec![
+                Arg::flag(Scope::Shared, "Werror=return-type"),
+                Arg::flag(Scope::Shared, "fsanitize=address,undefined"),
+                Arg::flag(Scope::Shared, "fno-sanitize-recover=all"),
+            ],
+        ),
+        (
+            "-pthread -pedantic -w -oModule.o",
+            vec![
+                Arg::flag(Scope::Shared, "pthread"),
+                Arg::flag(Scope::Shared, "pedantic"),
+                Arg::flag(Scope::Shared, "w"),
+                Arg::output(OutputKind::Object, "o", "Module.o"),
+            ],
+        ),
+    ];
+    for (line, expected) in cases.into_iter() {
+        let args = Vec::from_iter(line.split(' ').map(|x| x.to_string()));
+        assert_eq!(parse_arguments(&args).unwrap(), expected, "{}", line);
+    }
+}
+
+#[test]
+fn test_parse_argument_ubt_linux() {
+    // Real UnrealBuildTool Linux command line (response file content).
+    let args = Vec::from_iter(
+        "-c -pipe -msse2 -fno-math-errno -fdiagnostics-format=msvc -funwind-tables -gdwarf-4 \
+         -ggnu-pubnames -Wall -Werror -Wsequence-point -Wdelete-non-virtual-dtor \
+         -Wno-unused-private-field -Wno-tautological-compare -Wno-undefined-bool-conversion \
+         -Werror=return-type -fno-exceptions -fno-rtti -fvisibility-ms-compat \
+         -fvisibility-inlines-hidden -nostdinc++ -isystem ThirdParty/Linux/LibCxx/include \
+         -isystem ThirdParty/Linux/LibCxx/include/c++/v1 -O2 -fPIC -ftls-model=local-dynamic \
+         -target x86_64-unknown-linux-gnu --sysroot=/toolchain/x86_64-unknown-linux-gnu \
+         -DPLATFORM_EXCEPTIONS_DISABLED=1 -D_LINUX64 -DWITH_EDITOR=1 -U_FORTIFY_SOURCE \
+         -IRuntime/Core/Public -I Runtime/Core/Private -iquote Runtime/Engine/Private \
+         -x c++ -std=c++14 -stdlib=libc++ -include SharedPCH.Engine.h -MD \
+         -MF Module.Engine.cpp.d -o Module.Engine.cpp.o Module.Engine.cpp"
+            .split_whitespace()
+            .map(|x| x.to_string()),
+    );
+    let parsed = parse_arguments(&args).unwrap();
+    // System includes and dependency file are used only by preprocessor.
+    assert!(parsed.contains(&Arg::param(
+        Scope::Preprocessor,
+        "isystem",
+        "ThirdParty/Linux/LibCxx/include/c++/v1"
+    )));
+    assert!(parsed.contains(&Arg::param(
+        Scope::Preprocessor,
+        "MF",
+        "Module.Engine.cpp.d"
+    )));
+    // Macros and sysroot are required by compilation of rewritten source too.
+    assert!(parsed.contains(&Arg::param(Scope::Shared, "U", "_FORTIFY_SOURCE")));
+    assert!(parsed.contains(&Arg::flag(
+        Scope::Shared,
+        "-sysroot=/toolchain/x86_64-unknown-linux-gnu"
+    )));
+    assert_eq!(
+        parsed
+            .iter()
+            .filter(|arg| matches!(arg, Arg::Input { .. }))
+            .collect::<Vec<&Arg>>(),
+        vec![&Arg::input(InputKind::Source, "", "Module.Engine.cpp")]
+    );
+}
+
+#[test]
+fn test_load_arguments() {
+    use tempdir::TempDir;
+
+    let temp = TempDir::new("octobuild").unwrap();
+    fs::write(
+        temp.path().join("main.rsp"),
+        "-c -DNAME=\"a b\"\n@Intermediate/nested.rsp\n-o 'Module Core.o'\n",
+    )
+    .unwrap();
+    fs::create_dir(temp.path().join("Intermediate")).unwrap();
+    fs::write(
+        temp.path().join("Intermediate/nested.rsp"),
+        "-I Public\r\n@defines.rsp\r\n-Wall\r\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("Intermediate/defines.rsp"), "-DNESTED").unwrap();
+    fs::write(temp.path().join("loop.rsp"), "@loop.rsp").unwrap();
+
+    let base = Some(temp.path().to_path_buf());
+    let args = vec!["@main.rsp".to_string(), "Module.Core.cpp".to_string()];
+    assert_eq!(
+        load_arguments(&base, &args).unwrap(),
+        [
+            "-c",
+            "-DNAME=a b",
+            "-I",
+            "Public",
+            "-DNESTED",
+            "-Wall",
+            "-o",
+            "Module Core.o",
+            "Module.Core.cpp"
+        ]
+    );
+    assert!(load_arguments(&base, &["@loop.rsp".to_string()]).is_err());
+    assert!(load_arguments(&base, &["@missing.rsp".to_string()]).is_err());
+}
+
+#[cfg(test)]
+use crate::compiler::test::{split_args, test_command};
+
+#[test]
+fn test_create_tasks_precompiled() {
+    let command = test_command("clang++", Some("/build"));
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args("-x c++-header -O2 -o CorePrivatePCH.h.pch CorePrivatePCH.h"),
+    )
+    .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].language, "c++-header");
+    assert_eq!(tasks[0].output_object, Path::new("CorePrivatePCH.h.pch"));
+    assert_eq!(
+        tasks[0].shared.output_precompiled,
+        Some(PathBuf::from("/build/CorePrivatePCH.h.pch"))
+    );
+    assert_eq!(tasks[0].shared.input_precompiled, None);
+
+    let tasks = create_tasks(command.clone(), &split_args("-c Core.hpp")).unwrap();
+    assert_eq!(
+        tasks[0].shared.output_precompiled,
+        Some(PathBuf::from("/build/Core.hpp.gch"))
+    );
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args(
+            "-c -include-pch CorePrivatePCH.h.pch -include CorePrivatePCH.h -o Core.o Core.cpp",
+        ),
+    )
+    .unwrap();
+    assert_eq!(tasks[0].language, "c++");
+    assert_eq!(tasks[0].output_object, Path::new("Core.o"));
+    assert_eq!(tasks[0].shared.output_precompiled, None);
+    assert_eq!(
+        tasks[0].shared.input_precompiled,
+        Some(PathBuf::from("/build/CorePrivatePCH.h.pch"))
+    );
+
+    assert!(create_tasks(command, &split_args("-x c++-header -o a.pch a.h b.h")).is_err());
+}
+
+#[test]
+fn test_create_tasks_modules() {
+    let command = test_command("clang++", Some("/build"));
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args(
+            "-std=c++20 --precompile -fmodule-file=Core=Core.pcm -o Engine.pcm Engine.cppm",
+        ),
+    )
+    .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].language, "c++-module");
+    assert_eq!(tasks[0].output_object, Path::new("Engine.pcm"));
+    assert_eq!(
+        tasks[0].shared.output_module,
+        Some(PathBuf::from("/build/Engine.pcm"))
+    );
+    assert_eq!(
+        tasks[0].shared.input_modules,
+        [ModuleFile {
+            name: Some("Core".to_string()),
+            path: PathBuf::from("/build/Core.pcm"),
+        }]
+    );
+    assert!(is_precompile(&tasks[0].shared.args));
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args("-c -fmodule-file=Engine.pcm -fmodule-output=Game.pcm -o Game.o Game.cppm"),
+    )
+    .unwrap();
+    assert_eq!(tasks[0].output_object, Path::new("Game.o"));
+    assert_eq!(
+        tasks[0].shared.output_module,
+        Some(PathBuf::from("/build/Game.pcm"))
+    );
+    assert_eq!(tasks[0].shared.input_modules[0].name, None);
+    assert!(!is_precompile(&tasks[0].shared.args));
+
+    assert!(create_tasks(command, &split_args("-c -fprebuilt-module-path=. Game.cpp")).is_err());
+}
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::io;
+use std::sync::{Condvar, Mutex};
 
 use serde::{Deserialize, Serialize};
 
@@ -6,16 +8,24 @@ use crate::compiler::OutputInfo;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CompileRequest {
+    // Client identifier for fair scheduling (usually host name).
+    pub client: String,
     pub toolchain: String,
     pub args: Vec<String>,
     pub preprocessed_data: Vec<u8>,
     pub precompiled_hash: Option<String>,
+    // Uploaded compiled module interfaces: module name and file hash.
+    pub modules: Vec<(Option<String>, String)>,
+    // Uploaded toolchain package for builders without requested toolchain.
+    pub toolchain_package: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum CompileResponse {
     Success(OutputInfo, Vec<u8>),
     Err(String),
+    // Builder has no free slots for this client: try another builder or compile locally.
+    Busy,
 }
 
 impl From<Result<(OutputInfo, Vec<u8>), io::Error>> for CompileResponse {
@@ -26,3 +36,197 @@ impl From<Result<(OutputInfo, Vec<u8>), io::Error>> for CompileResponse {
         }
     }
 }
+
+// Compilation slots shared between clients.
+//
+// Every client can use no more than `client_limit` slots at once. When all slots are in use,
+// waiting requests are granted to the client with the fewest running tasks first.
+pub struct ClientScheduler {
+    slots: usize,
+    client_limit: usize,
+    queue_limit: usize,
+    state: Mutex<SchedulerState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    active: usize,
+    clients: HashMap<String, ClientState>,
+    next_ticket: u64,
+}
+
+#[derive(Default)]
+struct ClientState {
+    active: usize,
+    // Tickets of waiting requests in arrival order.
+    waiting: Vec<u64>,
+}
+
+pub struct ClientSlot<'a> {
+    scheduler: &'a ClientScheduler,
+    client: String,
+}
+
+impl ClientScheduler {
+    pub fn new(slots: usize, client_limit: usize) -> Self {
+        let slots = slots.max(1);
+        ClientScheduler {
+            slots,
+            client_limit: client_limit.max(1),
+            queue_limit: slots,
+            state: Mutex::new(SchedulerState::default()),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Check if client request would be accepted now (before uploading request dependencies).
+    pub fn has_capacity(&self, client: &str) -> bool {
+        self.accepts(&self.state.lock().unwrap(), client)
+    }
+
+    // Acquire compilation slot for client. Returns None if the client should not wait for this builder.
+    pub fn acquire(&self, client: &str) -> Option<ClientSlot<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if !self.accepts(&state, client) {
+            return None;
+        }
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state
+            .clients
+            .entry(client.to_string())
+            .or_default()
+            .waiting
+            .push(ticket);
+        while state.active >= self.slots || state.next_waiting() != Some(ticket) {
+            state = self.cond.wait(state).unwrap();
+        }
+        state.active += 1;
+        let entry = state.clients.get_mut(client).unwrap();
+        entry.waiting.retain(|t| *t != ticket);
+        entry.active += 1;
+        // Next waiting request may be granted too.
+        self.cond.notify_all();
+        Some(ClientSlot {
+            scheduler: self,
+            client: client.to_string(),
+        })
+    }
+
+    fn accepts(&self, state: &SchedulerState, client: &str) -> bool {
+        if let Some(entry) = state.clients.get(client) {
+            if entry.active + entry.waiting.len() >= self.client_limit {
+                return false;
+            }
+        }
+        let waiting: usize = state.clients.values().map(|c| c.waiting.len()).sum();
+        state.active + waiting < self.slots + self.queue_limit
+    }
+
+    fn release(&self, client: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+        let remove = {
+            let entry = state.clients.get_mut(client).unwrap();
+            entry.active -= 1;
+            entry.active == 0 && entry.waiting.is_empty()
+        };
+        if remove {
+            state.clients.remove(client);
+        }
+        self.cond.notify_all();
+    }
+}
+
+impl SchedulerState {
+    // Ticket of the request from client with fewest running tasks (oldest first).
+    fn next_waiting(&self) -> Option<u64> {
+        self.clients
+            .values()
+            .filter_map(|c| c.waiting.first().map(|t| (c.active, *t)))
+            .min()
+            .map(|(_, t)| t)
+    }
+}
+
+impl<'a> Drop for ClientSlot<'a> {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.client);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    // Wait until requests are queued by other threads.
+    fn wait_queued(scheduler: &ClientScheduler, count: usize) {
+        while scheduler
+            .state
+            .lock()
+            .unwrap()
+            .clients
+            .values()
+            .map(|c| c.waiting.len())
+            .sum::<usize>()
+            < count
+        {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_client_limit() {
+        let scheduler = ClientScheduler::new(4, 2);
+        let a1 = scheduler.acquire("a").unwrap();
+        let _a2 = scheduler.acquire("a").unwrap();
+        assert!(scheduler.acquire("a").is_none());
+        let _b1 = scheduler.acquire("b").unwrap();
+        drop(a1);
+        assert!(scheduler.acquire("a").is_some());
+    }
+
+    #[test]
+    fn test_refused_client() {
+        let scheduler = ClientScheduler::new(1, 1);
+        let _a1 = scheduler.acquire("a").unwrap();
+        assert!(!scheduler.has_capacity("a"));
+        assert!(scheduler.acquire("a").is_none());
+        // Refused clients are not tracked.
+        assert_eq!(scheduler.state.lock().unwrap().clients.len(), 1);
+        assert!(scheduler.has_capacity("b"));
+    }
+
+    #[test]
+    fn test_fair_queue() {
+        let scheduler = Arc::new(ClientScheduler::new(3, 3));
+        let _a1 = scheduler.acquire("a").unwrap();
+        let _a2 = scheduler.acquire("a").unwrap();
+        let b1 = scheduler.acquire("b").unwrap();
+
+        // Client "a" is waiting first, but "c" has fewer running tasks.
+        let (tx, rx) = mpsc::channel();
+        let mut threads = Vec::new();
+        for (client, queued) in &[("a", 1), ("c", 2)] {
+            let shared = scheduler.clone();
+            let tx = tx.clone();
+            threads.push(thread::spawn(move || {
+                let slot = shared.acquire(client).unwrap();
+                tx.send(client.to_string()).unwrap();
+                drop(slot);
+            }));
+            wait_queued(&scheduler, *queued);
+        }
+        drop(b1);
+        assert_eq!(rx.recv().unwrap(), "c");
+        assert_eq!(rx.recv().unwrap(), "a");
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::fs;
 use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Write};
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -10,15 +11,28 @@ use std::time::{Duration, Instant};
 use log::{trace, warn};
 use reqwest::blocking::Client;
 use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
 
 use crate::cache::FileHasher;
 use crate::cluster::builder::{CompileRequest, CompileResponse};
-use crate::cluster::common::{BuilderInfo, RPC_BUILDER_LIST, RPC_BUILDER_TASK, RPC_BUILDER_UPLOAD};
+use crate::cluster::common::{
+    current_platform, BuilderInfo, ChunkInfo, Chunker, PROTOCOL_VERSION, RPC_BUILDER_ASSEMBLE,
+    RPC_BUILDER_CHUNK, RPC_BUILDER_LIST, RPC_BUILDER_TASK, RPC_BUILDER_UPLOAD,
+};
+use crate::cluster::discovery::{unspecified_addr, DiscoveryListener};
 use crate::compiler::{
-    CommandInfo, CompilationTask, CompileStep, Compiler, OutputInfo, PreprocessResult, SharedState,
-    Toolchain,
+    CommandInfo, CompilationTask, CompileStep, Compiler, CompilerError, OutputInfo, OutputOrigin,
+    PreprocessResult, SharedState, Toolchain,
 };
+use crate::config::Config;
 use crate::io::memstream::MemStream;
+use crate::io::tempfile::TempFile;
+use crate::lazy::Lazy;
+
+const CHUNK_UPLOAD_RETRIES: usize = 3;
+const PACKAGE_DIR: &str = "toolchains";
+// Coordinator request timeout: unavailable coordinator must not stall compilation.
+const COORDINATOR_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub struct RemoteCompiler<C: Compiler> {
     shared: Arc<RemoteShared>,
@@ -32,58 +46,132 @@ struct RemoteSharedMut {
 
 struct RemoteShared {
     mutable: RwLock<RemoteSharedMut>,
-    base_url: Option<reqwest::Url>,
+    coordinators: Vec<reqwest::Url>,
+    discovery: Option<DiscoveryListener>,
+    client_id: String,
     client: Client,
+    // Client for coordinator requests with short timeout
+    coordinator_client: Client,
+    // Directory for toolchain packages
+    package_dir: PathBuf,
+    // Don't write packages into shared cache directory
+    package_readonly: bool,
 }
 
 struct RemoteToolchain {
     shared: Arc<RemoteShared>,
     local: Arc<dyn Toolchain>,
+    package: Lazy<Option<PathBuf>>,
+    // Compile task locally after remote failure
+    restart_on_local: bool,
 }
 
+// Remote compiler which doesn't restart failed remote tasks on local host.
+pub struct RemoteOnlyCompiler<'a, C: Compiler>(&'a RemoteCompiler<C>);
+
 impl<C: Compiler> RemoteCompiler<C> {
-    pub fn new(base_url: &Option<reqwest::Url>, compiler: C) -> Self {
+    pub fn new(config: &Config, compiler: C) -> Self {
+        let discovery = config.get_discovery_broadcast().and_then(|broadcast| {
+            // Listen on discovery port with the same address family as beacons.
+            let addr = unspecified_addr(&broadcast, broadcast.port());
+            DiscoveryListener::bind(addr, Some(broadcast))
+                .map_err(|e| warn!("Can't listen builder discovery on {}: {}", addr, e))
+                .ok()
+        });
         RemoteCompiler {
             shared: Arc::new(RemoteShared {
                 mutable: RwLock::new(RemoteSharedMut {
                     cooldown: Instant::now(),
                     builders: Arc::new(Vec::new()),
                 }),
-                base_url: base_url.as_ref().cloned(),
+                coordinators: config.coordinator.clone(),
+                discovery,
+                client_id: hostname::get()
+                    .ok()
+                    .and_then(|v| v.into_string().ok())
+                    .unwrap_or_else(|| "unknown".to_string()),
                 client: Client::new(),
+                coordinator_client: Client::builder()
+                    .connect_timeout(COORDINATOR_TIMEOUT)
+                    .timeout(COORDINATOR_TIMEOUT)
+                    .build()
+                    .unwrap(),
+                package_dir: config.cache_dir.join(PACKAGE_DIR),
+                package_readonly: config.cache_readonly,
             }),
             local: compiler,
         }
     }
+
+    // Compiler without remote build support.
+    pub fn local(&self) -> &C {
+        &self.local
+    }
+
+    // Compiler without local restart of failed remote tasks.
+    pub fn remote_only(&self) -> RemoteOnlyCompiler<'_, C> {
+        RemoteOnlyCompiler(self)
+    }
+
+    fn resolve(&self, command: &CommandInfo, restart_on_local: bool) -> Option<Arc<dyn Toolchain>> {
+        self.local
+            .resolve_toolchain(command)
+            .map(|local| -> Arc<dyn Toolchain> {
+                Arc::new(RemoteToolchain {
+                    shared: self.shared.clone(),
+                    local,
+                    package: Default::default(),
+                    restart_on_local,
+                })
+            })
+    }
 }
 
 impl RemoteSharedMut {
-    fn receive_builders(&self, base_url: &Option<reqwest::Url>) -> Result<Vec<BuilderInfo>, Error> {
-        match base_url {
-            Some(ref base_url) => {
-                let url = base_url.join(RPC_BUILDER_LIST).unwrap();
-                let mut response =
-                    reqwest::blocking::get(url).map_err(|e| Error::new(ErrorKind::Other, e))?;
-
-                bincode::deserialize_from(&mut response)
-                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    // Receive builder list from first available coordinator.
+    fn receive_builders(
+        &self,
+        client: &Client,
+        coordinators: &[reqwest::Url],
+    ) -> Result<Vec<BuilderInfo>, Error> {
+        let mut last_error = None;
+        for base_url in coordinators.iter() {
+            match receive_builder_list(client, base_url) {
+                Ok(builders) => return Ok(builders),
+                Err(e) => {
+                    trace!(
+                        "Can't receive builders from coordinator {}: {}",
+                        base_url,
+                        e
+                    );
+                    last_error = Some(e);
+                }
             }
+        }
+        match last_error {
+            Some(e) => Err(e),
             None => Ok(Vec::new()),
         }
     }
 }
 
+fn receive_builder_list(
+    client: &Client,
+    base_url: &reqwest::Url,
+) -> Result<Vec<BuilderInfo>, Error> {
+    let url = base_url.join(RPC_BUILDER_LIST).unwrap();
+    let mut response = client
+        .get(url)
+        .send()
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+    bincode::deserialize_from(&mut response).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
 impl<C: Compiler> Compiler for RemoteCompiler<C> {
     // Resolve toolchain for command execution.
     fn resolve_toolchain(&self, command: &CommandInfo) -> Option<Arc<dyn Toolchain>> {
-        self.local
-            .resolve_toolchain(command)
-            .map(|local| -> Arc<dyn Toolchain> {
-                Arc::new(RemoteToolchain {
-                    shared: self.shared.clone(),
-                    local,
-                })
-            })
+        self.resolve(command, true)
     }
 
     // Discovery local toolchains.
@@ -92,6 +180,18 @@ impl<C: Compiler> Compiler for RemoteCompiler<C> {
     }
 }
 
+impl<'a, C: Compiler> Compiler for RemoteOnlyCompiler<'a, C> {
+    // Resolve toolchain for command execution.
+    fn resolve_toolchain(&self, command: &CommandInfo) -> Option<Arc<dyn Toolchain>> {
+        self.0.resolve(command, false)
+    }
+
+    // Discovery local toolchains.
+    fn discovery_toolchains(&self) -> Vec<Arc<dyn Toolchain>> {
+        self.0.discovery_toolchains()
+    }
+}
+
 struct ReadWrapper<'a, R: 'a + Read>(&'a mut R);
 
 impl<'a, R: 'a + Read> Read for ReadWrapper<'a, R> {
@@ -101,31 +201,84 @@ impl<'a, R: 'a + Read> Read for ReadWrapper<'a, R> {
 }
 
 impl RemoteToolchain {
+    // Returns None if task can't be sent to builder.
     fn compile_remote(
         &self,
         state: &SharedState,
         task: &CompileStep,
-    ) -> Result<CompileResponse, Error> {
-        let name = self
-            .identifier()
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Can't get toolchain name"))?;
-        let addr = self
-            .remote_endpoint(&name)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Can't find helper for toolchain"))?;
+    ) -> Result<Option<CompileResponse>, Error> {
+        let name = match self.identifier() {
+            Some(name) => name,
+            None => {
+                trace!("Can't get toolchain name");
+                return Ok(None);
+            }
+        };
         if task.output_precompiled.is_some() {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Remote precompiled header generation is not supported",
-            ));
+            trace!("Remote precompiled header generation is not supported");
+            return Ok(None);
+        }
+        if task.output_module.is_some() {
+            trace!("Remote module interface generation is not supported");
+            return Ok(None);
         }
 
-        let base_url = get_base_url(&addr);
+        // Busy builders are skipped until all of them refuse the task.
+        let mut busy = Vec::new();
+        loop {
+            let (addr, builder, package) = match self.remote_endpoint(&name, &busy) {
+                Some(endpoint) => endpoint,
+                None if busy.is_empty() => {
+                    trace!("Can't find helper for toolchain");
+                    return Ok(None);
+                }
+                None => return Ok(Some(CompileResponse::Busy)),
+            };
+            let result = self.compile_on(state, task, &name, &addr, &package)?;
+            let result = match result {
+                CompileResponse::Success(mut output, content) => {
+                    output.agent = Some(builder);
+                    CompileResponse::Success(output, content)
+                }
+                result => result,
+            };
+            match result {
+                CompileResponse::Busy => {
+                    trace!("Builder {} is busy", addr);
+                    busy.push(addr);
+                }
+                _ => return Ok(Some(result)),
+            }
+        }
+    }
+
+    fn compile_on(
+        &self,
+        state: &SharedState,
+        task: &CompileStep,
+        name: &str,
+        addr: &SocketAddr,
+        package: &Option<PathBuf>,
+    ) -> Result<CompileResponse, Error> {
+        let base_url = get_base_url(addr);
+        // Don't upload files and preprocessed source to builder without free slots.
+        if !self.has_capacity(&base_url)? {
+            return Ok(CompileResponse::Busy);
+        }
+        let mut modules = Vec::with_capacity(task.input_modules.len());
+        for module in task.input_modules.iter() {
+            let hash = self.upload_file(state, &Some(module.path.clone()), &base_url)?;
+            modules.push((module.name.clone(), hash.unwrap()));
+        }
         // Send compilation request.
         let request = CompileRequest {
-            toolchain: name,
+            client: self.shared.client_id.clone(),
+            toolchain: name.to_string(),
             args: task.args.clone(),
             preprocessed_data: (&task.preprocessed).into(),
-            precompiled_hash: self.upload_precompiled(state, &task.input_precompiled, &base_url)?,
+            precompiled_hash: self.upload_file(state, &task.input_precompiled, &base_url)?,
+            modules,
+            toolchain_package: self.upload_file(state, package, &base_url)?,
         };
         let request_payload = bincode::serialize(&request).unwrap();
         let mut resp: reqwest::blocking::Response = self
@@ -138,53 +291,52 @@ impl RemoteToolchain {
         // Receive compilation result.
         let result: CompileResponse = bincode::deserialize_from(&mut resp)
             .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-        if let CompileResponse::Success(ref output, ref content) = result {
-            write_output(&task.output_object, output.success(), content)?;
+        match result {
+            CompileResponse::Success(ref output, ref content) => {
+                write_output(&task.output_object, output.success(), content)?;
+                state.statistic.inc_remote();
+            }
+            CompileResponse::Err(_) => state.statistic.inc_remote(),
+            CompileResponse::Busy => {}
         }
-        state.statistic.inc_remote();
         Ok(result)
     }
 
-    fn upload_precompiled(
+    // Upload precompiled header, compiled module interface or toolchain package.
+    fn upload_file(
         &self,
         state: &SharedState,
-        precompiled: &Option<PathBuf>,
+        file: &Option<PathBuf>,
         base_url: &reqwest::Url,
     ) -> Result<Option<String>, Error> {
-        match precompiled {
+        match file {
             Some(ref path) => {
-                // Get precompiled header file hash
+                // Get file hash
                 let meta = state.cache.file_hash(&path)?;
-                // Check is precompiled header uploaded
-                // todo: this is workaround for https://github.com/hyperium/hyper/issues/838
-                match self
-                    .shared
-                    .client
-                    .head(
-                        base_url
-                            .join(&format!("{}/{}", RPC_BUILDER_UPLOAD, meta.hash))
-                            .unwrap(),
-                    )
-                    .send()
-                    .map(|response| response.status())
-                    .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
-                {
-                    StatusCode::OK | StatusCode::ACCEPTED => return Ok(Some(meta.hash)),
-                    _ => {}
+                // Check is file uploaded
+                if self.is_uploaded(base_url, RPC_BUILDER_UPLOAD, &meta.hash)? {
+                    return Ok(Some(meta.hash));
                 }
-                let file = File::open(path)?;
-                // Upload precompiled header
+                // Upload only chunks missing on builder
+                let chunks = Chunker::default().split(&mut File::open(path)?)?;
+                for chunk in chunks.iter() {
+                    if !self.is_uploaded(base_url, RPC_BUILDER_CHUNK, &chunk.hash)?
+                        && !self.upload_chunk(path, chunk, base_url)?
+                    {
+                        // Builder doesn't support chunks: upload whole file
+                        return self.upload_whole(path, &meta.hash, meta.size, base_url);
+                    }
+                }
+                // Assemble file from chunks
                 match self
                     .shared
                     .client
                     .post(
                         base_url
-                            .join(&format!("{}/{}", RPC_BUILDER_UPLOAD, meta.hash))
+                            .join(&format!("{}/{}", RPC_BUILDER_ASSEMBLE, meta.hash))
                             .unwrap(),
                     )
-                    // todo: this is workaround for https://github.com/hyperium/hyper/issues/838
-                    //.header(Expect::Continue)
-                    .body(reqwest::blocking::Body::sized(file, meta.size))
+                    .body(bincode::serialize(&chunks).unwrap())
                     .send()
                     .map(|response| response.status())
                     .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
@@ -192,7 +344,7 @@ impl RemoteToolchain {
                     StatusCode::OK | StatusCode::ACCEPTED => Ok(Some(meta.hash)),
                     status => Err(Error::new(
                         ErrorKind::BrokenPipe,
-                        format!("Can't upload precompiled header: {}", status),
+                        format!("Can't upload file {}: {}", path.display(), status),
                     )),
                 }
             }
@@ -200,7 +352,134 @@ impl RemoteToolchain {
         }
     }
 
+    fn has_capacity(&self, base_url: &reqwest::Url) -> Result<bool, Error> {
+        match self
+            .shared
+            .client
+            .head(
+                base_url
+                    .join(&format!("{}/{}", RPC_BUILDER_TASK, self.shared.client_id))
+                    .unwrap(),
+            )
+            .send()
+            .map(|response| response.status())
+            .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
+        {
+            StatusCode::SERVICE_UNAVAILABLE => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    fn is_uploaded(&self, base_url: &reqwest::Url, rpc: &str, hash: &str) -> Result<bool, Error> {
+        // todo: this is workaround for https://github.com/hyperium/hyper/issues/838
+        match self
+            .shared
+            .client
+            .head(base_url.join(&format!("{}/{}", rpc, hash)).unwrap())
+            .send()
+            .map(|response| response.status())
+            .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
+        {
+            StatusCode::OK | StatusCode::ACCEPTED => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn upload_whole(
+        &self,
+        path: &Path,
+        hash: &str,
+        size: u64,
+        base_url: &reqwest::Url,
+    ) -> Result<Option<String>, Error> {
+        match self
+            .shared
+            .client
+            .post(
+                base_url
+                    .join(&format!("{}/{}", RPC_BUILDER_UPLOAD, hash))
+                    .unwrap(),
+            )
+            .body(reqwest::blocking::Body::sized(File::open(path)?, size))
+            .send()
+            .map(|response| response.status())
+            .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
+        {
+            StatusCode::OK | StatusCode::ACCEPTED => Ok(Some(hash.to_string())),
+            status => Err(Error::new(
+                ErrorKind::BrokenPipe,
+                format!("Can't upload file {}: {}", path.display(), status),
+            )),
+        }
+    }
+
+    // Returns false if builder doesn't support chunk upload.
+    fn upload_chunk(
+        &self,
+        path: &Path,
+        chunk: &ChunkInfo,
+        base_url: &reqwest::Url,
+    ) -> Result<bool, Error> {
+        let url = base_url
+            .join(&format!("{}/{}", RPC_BUILDER_CHUNK, chunk.hash))
+            .unwrap();
+        let mut last_error = None;
+        // Dropped connection costs only current chunk
+        for _ in 0..CHUNK_UPLOAD_RETRIES {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(chunk.offset))?;
+            match self
+                .shared
+                .client
+                .post(url.clone())
+                .body(reqwest::blocking::Body::sized(
+                    file.take(chunk.size),
+                    chunk.size,
+                ))
+                .send()
+                .map(|response| response.status())
+            {
+                Ok(StatusCode::OK) | Ok(StatusCode::ACCEPTED) => return Ok(true),
+                Ok(StatusCode::NOT_FOUND) => return Ok(false),
+                Ok(status) => {
+                    return Err(Error::new(
+                        ErrorKind::BrokenPipe,
+                        format!("Can't upload precompiled header chunk: {}", status),
+                    ));
+                }
+                Err(e) => {
+                    trace!("Retry chunk upload {}: {}", chunk.hash, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(Error::new(ErrorKind::BrokenPipe, last_error.unwrap()))
+    }
+
     fn builders(&self) -> Arc<Vec<BuilderInfo>> {
+        let builders = self.coordinator_builders();
+        match self.shared.discovery {
+            Some(ref discovery) => {
+                // Builders from LAN beacons are always fresh: merge them on every request.
+                // Probe is needed only when coordinators don't know any builder.
+                let discovered = if builders.is_empty() {
+                    discovery.probe_builders()
+                } else {
+                    discovery.builders()
+                };
+                let mut result = builders.as_ref().clone();
+                for builder in discovered {
+                    if !result.iter().any(|b| b.endpoint == builder.endpoint) {
+                        result.push(builder);
+                    }
+                }
+                Arc::new(result)
+            }
+            None => builders,
+        }
+    }
+
+    fn coordinator_builders(&self) -> Arc<Vec<BuilderInfo>> {
         let now = Instant::now();
         {
             let holder = self.shared.mutable.read().unwrap();
@@ -213,7 +492,9 @@ impl RemoteToolchain {
             if holder.cooldown >= now {
                 return holder.builders.clone();
             }
-            match holder.receive_builders(&self.shared.base_url) {
+            match holder
+                .receive_builders(&self.shared.coordinator_client, &self.shared.coordinators)
+            {
                 Ok(builders) => {
                     holder.builders = Arc::new(builders);
                     holder.cooldown = now + Duration::from_secs(5);
@@ -227,11 +508,78 @@ impl RemoteToolchain {
         }
     }
     // Resolve toolchain for command execution.
-    fn remote_endpoint(&self, toolchain_name: &str) -> Option<SocketAddr> {
+    // Returns builder endpoint, builder name and toolchain package if builder hasn't required toolchain.
+    fn remote_endpoint(
+        &self,
+        toolchain_name: &str,
+        exclude: &[SocketAddr],
+    ) -> Option<(SocketAddr, String, Option<PathBuf>)> {
         let name = toolchain_name.to_string();
         let all_builders = self.builders();
-        let builder = get_random_builder(&all_builders, |b| b.toolchains.contains(&name))?;
-        SocketAddr::from_str(&builder.endpoint).ok()
+        let available = |b: &BuilderInfo| {
+            b.protocol == PROTOCOL_VERSION
+                && SocketAddr::from_str(&b.endpoint)
+                    .map(|addr| !exclude.contains(&addr))
+                    .unwrap_or(false)
+        };
+        // Prefer builders with installed toolchain.
+        if let Some(builder) = get_random_builder(&all_builders, |b| {
+            b.toolchains.contains(&name) && available(b)
+        }) {
+            return SocketAddr::from_str(&builder.endpoint)
+                .ok()
+                .map(|addr| (addr, builder.name.clone(), None));
+        }
+        let platform = current_platform();
+        if !all_builders
+            .iter()
+            .any(|b| b.platform == platform && available(b))
+        {
+            return None;
+        }
+        let package = self.package(&name)?;
+        let builder =
+            get_random_builder(&all_builders, |b| b.platform == platform && available(b))?;
+        SocketAddr::from_str(&builder.endpoint)
+            .ok()
+            .map(|addr| (addr, builder.name.clone(), Some(package)))
+    }
+
+    fn package(&self, name: &str) -> Option<PathBuf> {
+        self.package.get(|| {
+            self.create_package(name)
+                .map_err(|e| warn!("Can't create toolchain package for {}: {}", name, e))
+                .ok()
+                .and_then(|v| v)
+        })
+    }
+
+    // Create toolchain package once for every toolchain identifier and compiler binary content.
+    fn create_package(&self, name: &str) -> Result<Option<PathBuf>, Error> {
+        let binary = match self.local.package_binary() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let mut hasher = Sha256::new();
+        Digest::input(&mut hasher, name.as_bytes());
+        io::copy(&mut File::open(binary)?, &mut hasher)?;
+        let path = self
+            .shared
+            .package_dir
+            .join(hex::encode(hasher.result()) + ".tar");
+        if path.exists() {
+            return Ok(Some(path));
+        }
+        if self.shared.package_readonly {
+            return Ok(None);
+        }
+        fs::create_dir_all(&self.shared.package_dir)?;
+        let temp = TempFile::new_in(&self.shared.package_dir, ".tmp");
+        if !self.local.create_package(&mut File::create(temp.path())?)? {
+            return Ok(None);
+        }
+        fs::rename(temp.path(), &path)?;
+        Ok(Some(path))
     }
 }
 
@@ -245,7 +593,7 @@ impl Toolchain for RemoteToolchain {
         &self,
         command: CommandInfo,
         args: &[String],
-    ) -> Result<Vec<CompilationTask>, String> {
+    ) -> Result<Vec<CompilationTask>, CompilerError> {
         self.local.create_tasks(command, args)
     }
 
@@ -269,14 +617,23 @@ impl Toolchain for RemoteToolchain {
 
     fn compile_step(&self, state: &SharedState, task: CompileStep) -> Result<OutputInfo, Error> {
         match self.compile_remote(state, &task) {
-            Ok(response) => match response {
-                CompileResponse::Success(output, _) => Ok(output),
+            Ok(Some(response)) => match response {
+                CompileResponse::Success(mut output, _) => {
+                    output.origin = OutputOrigin::Remote;
+                    Ok(output)
+                }
                 CompileResponse::Err(err) => Err(Error::new(ErrorKind::Other, err)),
+                CompileResponse::Busy => {
+                    trace!("Fallback to local build: all builders are busy");
+                    self.local.compile_step(state, task)
+                }
             },
-            Err(e) => {
+            Ok(None) => self.local.compile_step(state, task),
+            Err(e) if self.restart_on_local => {
                 trace!("Fallback to local build: {}", e);
                 self.local.compile_step(state, task)
             }
+            Err(e) => Err(e),
         }
     }
 }
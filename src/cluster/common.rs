@@ -1,13 +1,27 @@
+use std::env;
+use std::io::{Error, Read};
+
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-pub const RPC_BUILDER_UPDATE: &str = "/rpc/v1/builder/update";
-pub const RPC_BUILDER_LIST: &str = "/rpc/v1/builder/list";
+use crate::utils::DEFAULT_BUF_SIZE;
+
+pub const RPC_BUILDER_UPDATE: &str = "/rpc/v2/builder/update";
+pub const RPC_BUILDER_LIST: &str = "/rpc/v2/builder/list";
+pub const RPC_COORDINATOR_SYNC: &str = "/rpc/v2/coordinator/sync";
+
+pub const RPC_BUILDER_INFO: &str = "/rpc/v2/builder/info";
+pub const RPC_BUILDER_TASK: &str = "/rpc/v2/builder/task";
+pub const RPC_BUILDER_UPLOAD: &str = "/rpc/v2/builder/upload";
+pub const RPC_BUILDER_CHUNK: &str = "/rpc/v2/builder/chunk";
+pub const RPC_BUILDER_ASSEMBLE: &str = "/rpc/v2/builder/assemble";
 
-pub const RPC_BUILDER_TASK: &str = "/rpc/v1/builder/task";
-pub const RPC_BUILDER_UPLOAD: &str = "/rpc/v1/builder/upload";
+// Version of RPC payloads: builders with other version are ignored by clients.
+pub const PROTOCOL_VERSION: u32 = 2;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct BuilderInfo {
     // Agent name
     pub name: String,
@@ -17,9 +31,13 @@ pub struct BuilderInfo {
     pub version: String,
     // Agent toolchain list
     pub toolchains: Vec<String>,
+    // Agent platform for uploaded toolchain packages
+    pub platform: String,
+    // Agent RPC protocol version
+    pub protocol: u32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct BuilderInfoUpdate {
     // Hidden unique Id for builder update information
     pub guid: String,
@@ -27,6 +45,11 @@ pub struct BuilderInfoUpdate {
     pub info: BuilderInfo,
 }
 
+// Platform identifier: toolchain packages can be executed only on the same platform.
+pub fn current_platform() -> String {
+    format!("{}-{}", env::consts::OS, env::consts::ARCH)
+}
+
 impl BuilderInfoUpdate {
     pub fn new(info: BuilderInfo) -> Self {
         BuilderInfoUpdate {
@@ -35,3 +58,172 @@ impl BuilderInfoUpdate {
         }
     }
 }
+
+// Content-defined part of uploaded file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChunkInfo {
+    // Chunk content hash (sha256)
+    pub hash: String,
+    // Chunk offset in file
+    pub offset: u64,
+    // Chunk size
+    pub size: u64,
+}
+
+// Content-defined chunking parameters (gear rolling hash).
+//
+// Chunk boundaries depend only on the nearby content, so an insertion or removal in the
+// middle of a file changes only the surrounding chunks and the rest can be reused.
+pub struct Chunker {
+    // Minimal chunk size
+    pub min_size: u64,
+    // Maximal chunk size
+    pub max_size: u64,
+    // Boundary mask: average chunk size is near min_size + mask + 1
+    pub mask: u64,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker {
+            min_size: 512 * 1024,
+            max_size: 8 * 1024 * 1024,
+            mask: (1 << 21) - 1,
+        }
+    }
+}
+
+lazy_static! {
+    static ref GEAR: [u64; 256] = {
+        // Fixed table: client and builder must produce the same boundaries.
+        let mut table = [0u64; 256];
+        let mut seed: u64 = 0x6f63_746f_6275_696c;
+        for item in table.iter_mut() {
+            // splitmix64
+            seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            *item = z ^ (z >> 31);
+        }
+        table
+    };
+}
+
+impl Chunker {
+    pub fn split<R: Read>(&self, reader: &mut R) -> Result<Vec<ChunkInfo>, Error> {
+        let mut chunks = Vec::new();
+        let mut buf: [u8; DEFAULT_BUF_SIZE] = [0; DEFAULT_BUF_SIZE];
+        let mut hasher = Sha256::new();
+        let mut gear: u64 = 0;
+        let mut offset: u64 = 0;
+        let mut size: u64 = 0;
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            let mut begin = 0;
+            for (index, byte) in buf[0..len].iter().enumerate() {
+                gear = (gear << 1).wrapping_add(GEAR[*byte as usize]);
+                size += 1;
+                if (size >= self.min_size && gear & self.mask == 0) || size >= self.max_size {
+                    Digest::input(&mut hasher, &buf[begin..=index]);
+                    chunks.push(ChunkInfo {
+                        hash: hex::encode(hasher.result_reset()),
+                        offset,
+                        size,
+                    });
+                    begin = index + 1;
+                    offset += size;
+                    size = 0;
+                    gear = 0;
+                }
+            }
+            Digest::input(&mut hasher, &buf[begin..len]);
+        }
+        if size > 0 {
+            chunks.push(ChunkInfo {
+                hash: hex::encode(hasher.result()),
+                offset,
+                size,
+            });
+        }
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn test_chunker() -> Chunker {
+        Chunker {
+            min_size: 256,
+            max_size: 4096,
+            mask: (1 << 10) - 1,
+        }
+    }
+
+    fn test_data(size: usize) -> Vec<u8> {
+        let mut seed: u32 = 42;
+        (0..size)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunker_covers_data() {
+        let data = test_data(100_000);
+        let chunks = test_chunker().split(&mut Cursor::new(&data)).unwrap();
+        assert!(chunks.len() > 1);
+        let mut offset = 0;
+        for chunk in chunks.iter() {
+            assert_eq!(chunk.offset, offset);
+            assert!(chunk.size <= 4096);
+            let begin = chunk.offset as usize;
+            let end = begin + chunk.size as usize;
+            assert_eq!(
+                chunk.hash,
+                crate::utils::hash_stream(&mut Cursor::new(&data[begin..end])).unwrap()
+            );
+            offset += chunk.size;
+        }
+        assert_eq!(offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_chunker_empty() {
+        assert_eq!(
+            test_chunker().split(&mut Cursor::new(Vec::new())).unwrap(),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn test_chunker_shift_resistance() {
+        let data = test_data(100_000);
+        let mut shifted = b"inserted prefix".to_vec();
+        shifted.extend_from_slice(&data);
+
+        let original: Vec<String> = test_chunker()
+            .split(&mut Cursor::new(&data))
+            .unwrap()
+            .into_iter()
+            .map(|c| c.hash)
+            .collect();
+        let changed: Vec<String> = test_chunker()
+            .split(&mut Cursor::new(&shifted))
+            .unwrap()
+            .into_iter()
+            .map(|c| c.hash)
+            .collect();
+        let reused = changed.iter().filter(|h| original.contains(h)).count();
+        assert!(reused + 2 >= original.len());
+    }
+}
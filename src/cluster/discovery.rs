@@ -0,0 +1,345 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::trace;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::cluster::common::{BuilderInfo, BuilderInfoUpdate};
+
+const BEACON_HEADER: &[u8] = b"OBDB\x00\x01";
+// Request for immediate beacon from all builders.
+const PROBE_HEADER: &[u8] = b"OBDP\x00\x01";
+// Time for builders to answer discovery probe.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+// Builder information lifetime without beacons.
+const BEACON_TIMEOUT: Duration = Duration::from_secs(5);
+// Listener shutdown check interval.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(200);
+const MAX_BEACON_SIZE: usize = 0x10000;
+
+// Periodically sent builder announcement.
+pub struct BeaconSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    payload: Vec<u8>,
+}
+
+// Answers discovery probes with builder announcement.
+pub struct ProbeResponder {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// Collects builder announcements without coordinator.
+pub struct DiscoveryListener {
+    shared: Arc<DiscoveryShared>,
+    local_addr: SocketAddr,
+    // Probe target (probe is sent only once and only when no builders are known).
+    probe: Mutex<Option<SocketAddr>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct DiscoveryShared {
+    done: AtomicBool,
+    builders: RwLock<Vec<DiscoveredBuilder>>,
+}
+
+struct DiscoveredBuilder {
+    guid: String,
+    info: BuilderInfo,
+    timeout: Instant,
+}
+
+impl BeaconSender {
+    // Create beacon sender for broadcast address (like 255.255.255.255:3001).
+    pub fn new(target: SocketAddr, update: &BuilderInfoUpdate) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(unspecified_addr(&target, 0))?;
+        socket.set_broadcast(true)?;
+        let mut payload = BEACON_HEADER.to_vec();
+        bincode::serialize_into(&mut payload, update)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(BeaconSender {
+            socket,
+            target,
+            payload,
+        })
+    }
+
+    pub fn send(&self) -> Result<(), Error> {
+        self.socket.send_to(&self.payload, self.target).map(|_| ())
+    }
+
+    // Answer probes from starting clients (probes are sent to discovery port).
+    pub fn responder(&self) -> Result<ProbeResponder, Error> {
+        let socket = bind_shared(unspecified_addr(&self.target, self.target.port()))?;
+        let payload = self.payload.clone();
+        let done = Arc::new(AtomicBool::new(false));
+        let thread_done = done.clone();
+        let thread = thread::spawn(move || {
+            receive_loop(&socket, &thread_done, |data, sender| {
+                if data.starts_with(PROBE_HEADER) {
+                    if let Err(e) = socket.send_to(&payload, sender) {
+                        trace!("Can't answer discovery probe from {}: {}", sender, e);
+                    }
+                }
+            });
+        });
+        Ok(ProbeResponder {
+            done,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ProbeResponder {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            drop(t.join());
+        }
+    }
+}
+
+impl DiscoveryListener {
+    // Listen beacons on address shared with other processes. Probe target is used for
+    // immediate answer (short-lived process can't wait for periodic beacons).
+    pub fn bind(addr: SocketAddr, probe: Option<SocketAddr>) -> Result<Self, Error> {
+        let socket = bind_shared(addr)?;
+        let local_addr = socket.local_addr()?;
+        let shared = Arc::new(DiscoveryShared {
+            done: AtomicBool::new(false),
+            builders: RwLock::new(Vec::new()),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            receive_loop(&socket, &thread_shared.done, |data, sender| {
+                thread_shared.receive(data, sender)
+            });
+        });
+        Ok(DiscoveryListener {
+            shared,
+            local_addr,
+            probe: Mutex::new(probe),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Alive builders (probe builders if none is known yet).
+    pub fn probe_builders(&self) -> Vec<BuilderInfo> {
+        let builders = self.builders();
+        if !builders.is_empty() {
+            return builders;
+        }
+        // Concurrent callers wait for the same probe.
+        let mut probe = self.probe.lock().unwrap();
+        if let Some(target) = probe.take() {
+            if let Err(e) = self.probe(target) {
+                trace!("Can't probe builders on {}: {}", target, e);
+            }
+        }
+        self.builders()
+    }
+
+    // Send probe and wait for first answer (answers are sent back to probe socket).
+    fn probe(&self, target: SocketAddr) -> Result<(), Error> {
+        let socket = UdpSocket::bind(unspecified_addr(&target, 0))?;
+        socket.set_broadcast(true)?;
+        socket.send_to(PROBE_HEADER, target)?;
+        let deadline = Instant::now() + PROBE_TIMEOUT;
+        let mut buf = vec![0; MAX_BEACON_SIZE];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            socket.set_read_timeout(Some(deadline - now))?;
+            let (size, sender) = socket.recv_from(&mut buf)?;
+            self.shared.receive(&buf[0..size], sender);
+            if !self.shared.builders.read().unwrap().is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    // Alive builders.
+    pub fn builders(&self) -> Vec<BuilderInfo> {
+        let holder = self.shared.builders.read().unwrap();
+        let now = Instant::now();
+        holder
+            .iter()
+            .filter(|e| e.timeout >= now)
+            .map(|e| e.info.clone())
+            .collect()
+    }
+}
+
+impl DiscoveryShared {
+    fn receive(&self, data: &[u8], sender: SocketAddr) {
+        match parse_beacon(data, sender) {
+            Ok(update) => self.update(update),
+            Err(e) => trace!("Ignore invalid beacon from {}: {}", sender, e),
+        }
+    }
+
+    fn update(&self, update: BuilderInfoUpdate) {
+        let mut holder = self.builders.write().unwrap();
+        let now = Instant::now();
+        holder.retain(|e| (e.guid != update.guid) && (e.timeout >= now));
+        holder.push(DiscoveredBuilder {
+            guid: update.guid,
+            info: update.info,
+            timeout: now + BEACON_TIMEOUT,
+        });
+    }
+}
+
+impl Drop for DiscoveryListener {
+    fn drop(&mut self) {
+        self.shared.done.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            drop(t.join());
+        }
+    }
+}
+
+// Unspecified address of the same family as given address.
+pub fn unspecified_addr(addr: &SocketAddr, port: u16) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port),
+    }
+}
+
+// Bind UDP socket to address which can be used by several processes on same host
+// (broadcast packets are delivered to all of them).
+fn bind_shared(addr: SocketAddr) -> Result<UdpSocket, Error> {
+    let domain = match addr {
+        SocketAddr::V4(_) => Domain::ipv4(),
+        SocketAddr::V6(_) => Domain::ipv6(),
+    };
+    let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    let socket = socket.into_udp_socket();
+    socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+    Ok(socket)
+}
+
+fn receive_loop<F: FnMut(&[u8], SocketAddr)>(
+    socket: &UdpSocket,
+    done: &AtomicBool,
+    mut handler: F,
+) {
+    let mut buf = vec![0; MAX_BEACON_SIZE];
+    while !done.load(Ordering::Relaxed) {
+        match socket.recv_from(&mut buf) {
+            Ok((size, sender)) => handler(&buf[0..size], sender),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => {
+                trace!("Can't receive beacon: {}", e);
+                thread::sleep(RECEIVE_TIMEOUT);
+            }
+        }
+    }
+}
+
+fn parse_beacon(data: &[u8], sender: SocketAddr) -> Result<BuilderInfoUpdate, Error> {
+    if !data.starts_with(BEACON_HEADER) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "unexpected beacon header",
+        ));
+    }
+    let mut update: BuilderInfoUpdate = bincode::deserialize(&data[BEACON_HEADER.len()..])
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    // Fix inspecified endpoint IP address.
+    let endpoint = SocketAddr::from_str(&update.info.endpoint)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    if endpoint.ip().is_unspecified() {
+        update.info.endpoint = SocketAddr::new(sender.ip(), endpoint.port()).to_string();
+    }
+    Ok(update)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wait_builders(listener: &DiscoveryListener) -> Vec<BuilderInfo> {
+        for _ in 0..50 {
+            let builders = listener.builders();
+            if !builders.is_empty() {
+                return builders;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Vec::new()
+    }
+
+    fn builder_update() -> BuilderInfoUpdate {
+        BuilderInfoUpdate::new(BuilderInfo {
+            name: "builder".to_string(),
+            endpoint: "0.0.0.0:1234".to_string(),
+            version: "test".to_string(),
+            toolchains: vec!["clang".to_string()],
+            platform: "test".to_string(),
+            protocol: 2,
+        })
+    }
+
+    #[test]
+    fn test_discovery_probe() {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let target = SocketAddr::from_str(&format!("127.0.0.1:{}", port)).unwrap();
+        let sender = BeaconSender::new(target, &builder_update()).unwrap();
+        let _responder = sender.responder().unwrap();
+        // Discovery port is shared between processes.
+        drop(bind_shared(target).unwrap());
+
+        // Builder is known without waiting for periodic beacon.
+        let listener =
+            DiscoveryListener::bind(SocketAddr::from_str("127.0.0.1:0").unwrap(), Some(target))
+                .unwrap();
+        assert!(listener.builders().is_empty());
+        let builders = listener.probe_builders();
+        assert_eq!(builders.len(), 1);
+        assert_eq!(builders[0].name, "builder");
+        assert_eq!(builders[0].endpoint, "127.0.0.1:1234");
+    }
+
+    #[test]
+    fn test_discovery_loopback() {
+        let listener =
+            DiscoveryListener::bind(SocketAddr::from_str("127.0.0.1:0").unwrap(), None).unwrap();
+        let sender = BeaconSender::new(listener.local_addr(), &builder_update()).unwrap();
+        // Garbage must be ignored.
+        sender
+            .socket
+            .send_to(b"garbage", listener.local_addr())
+            .unwrap();
+        sender.send().unwrap();
+        sender.send().unwrap();
+
+        let builders = wait_builders(&listener);
+        assert_eq!(builders.len(), 1);
+        assert_eq!(builders[0].name, "builder");
+        assert_eq!(builders[0].endpoint, "127.0.0.1:1234");
+        assert_eq!(builders[0].toolchains, vec!["clang".to_string()]);
+    }
+}
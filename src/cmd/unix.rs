@@ -34,7 +34,7 @@ pub fn parse(cmd: &str) -> Result<Vec<String>, Error> {
             '"' if quote == Quote::Double => {
                 quote = Quote::None;
             }
-            ' ' | '\t' if quote == Quote::None => {
+            ' ' | '\t' | '\r' | '\n' if quote == Quote::None => {
                 if data {
                     args.push(arg);
                     arg = String::new();
@@ -148,3 +148,11 @@ fn test_parse_10() {
         ["begin", "some text", "end"]
     );
 }
+
+#[test]
+fn test_parse_multiline() {
+    assert_eq!(
+        parse("-c\r\n-o \"a b.o\"\n\t'c d.cpp'\n").unwrap(),
+        ["-c", "-o", "a b.o", "c d.cpp"]
+    );
+}
@@ -0,0 +1,205 @@
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use glob::Pattern;
+use log::warn;
+use petgraph::Graph;
+use serde::Deserialize;
+
+use crate::cmd;
+use crate::compiler::{CommandEnv, CommandInfo, Compiler};
+use crate::worker::{BuildAction, BuildGraph, BuildTask};
+
+// Compilation database entry (compile_commands.json).
+#[derive(Debug, Deserialize)]
+pub struct CompileCommand {
+    // Working directory
+    pub directory: PathBuf,
+    // Source file (relative to working directory)
+    pub file: PathBuf,
+    // Command line as single string
+    #[serde(default)]
+    pub command: Option<String>,
+    // Command line as argument list (preferred over command)
+    #[serde(default)]
+    pub arguments: Option<Vec<String>>,
+    // Output file
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum CompDbError {
+    CommandNotFound(PathBuf),
+    JsonError(serde_json::Error),
+}
+
+impl Display for CompDbError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), ::std::fmt::Error> {
+        match self {
+            CompDbError::CommandNotFound(ref path) => {
+                write!(f, "command line not found for file: {}", path.display())
+            }
+            CompDbError::JsonError(ref e) => write!(f, "json reading error: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for CompDbError {
+    fn description(&self) -> &str {
+        match self {
+            CompDbError::CommandNotFound(_) => "command line not found",
+            CompDbError::JsonError(_) => "json reading error",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn ::std::error::Error> {
+        None
+    }
+}
+
+impl CompileCommand {
+    pub fn source(&self) -> PathBuf {
+        self.directory.join(&self.file)
+    }
+
+    pub fn args(&self) -> Result<Vec<String>, Error> {
+        let args = match (&self.arguments, &self.command) {
+            (Some(ref arguments), _) => arguments.clone(),
+            (None, Some(ref command)) => cmd::native::parse(command)?,
+            (None, None) => Vec::new(),
+        };
+        if args.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                CompDbError::CommandNotFound(self.file.clone()),
+            ));
+        }
+        Ok(args)
+    }
+
+    // Glob matches source file path as written or resolved by working directory.
+    pub fn matches(&self, pattern: &Pattern) -> bool {
+        pattern.matches_path(&self.file) || pattern.matches_path(&self.source())
+    }
+}
+
+pub fn parse<R: Read>(reader: R) -> Result<Vec<CompileCommand>, Error> {
+    serde_json::from_reader(reader)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, CompDbError::JsonError(e)))
+}
+
+// Keep entries matched by any pattern (all entries without patterns).
+pub fn filter(commands: Vec<CompileCommand>, patterns: &[Pattern]) -> Vec<CompileCommand> {
+    if patterns.is_empty() {
+        return commands;
+    }
+    commands
+        .into_iter()
+        .filter(|command| patterns.iter().any(|p| command.matches(p)))
+        .collect()
+}
+
+// Compilation database entries are independent: graph has no edges.
+// Entries without usable command are skipped.
+pub fn create_graph<C: Compiler>(
+    compiler: &C,
+    commands: &[CompileCommand],
+) -> Result<BuildGraph, Error> {
+    let env = Arc::new(env::vars().collect::<CommandEnv>());
+    let mut graph: BuildGraph = Graph::new();
+    for command in commands.iter() {
+        let args = match command.args() {
+            Ok(args) => args,
+            Err(e) => {
+                warn!(
+                    "Skip compilation database entry {}: {}",
+                    command.file.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        let info = CommandInfo {
+            program: PathBuf::from(&args[0]),
+            current_dir: Some(command.directory.clone()),
+            env: env.clone(),
+        };
+        let title = command.file.display().to_string();
+        for action in BuildAction::create_tasks(compiler, info, &args[1..], &title) {
+            graph.add_node(Arc::new(BuildTask {
+                title: title.clone(),
+                action,
+                options: Default::default(),
+            }));
+        }
+    }
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use glob::Pattern;
+
+    use crate::compiler::CompilerGroup;
+
+    use super::{create_graph, filter, parse};
+
+    #[test]
+    fn test_parse_compdb() {
+        let json = r#"[
+            {
+                "directory": "/build",
+                "file": "src/foo.cpp",
+                "arguments": ["clang++", "-c", "-o", "foo.o", "src/foo.cpp"]
+            },
+            {
+                "directory": "/build",
+                "file": "/src/bar.c",
+                "command": "clang -c -o \"bar 1.o\" /src/bar.c",
+                "output": "bar 1.o"
+            }
+        ]"#;
+        let commands = parse(json.as_bytes()).unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].source(), PathBuf::from("/build/src/foo.cpp"));
+        assert_eq!(
+            commands[0].args().unwrap(),
+            vec!["clang++", "-c", "-o", "foo.o", "src/foo.cpp"]
+        );
+        assert_eq!(commands[1].source(), PathBuf::from("/src/bar.c"));
+        assert_eq!(
+            commands[1].args().unwrap(),
+            vec!["clang", "-c", "-o", "bar 1.o", "/src/bar.c"]
+        );
+        assert_eq!(commands[1].output, Some(PathBuf::from("bar 1.o")));
+
+        let filtered = filter(commands, &[Pattern::new("/build/**/*.cpp").unwrap()]);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].file, PathBuf::from("src/foo.cpp"));
+    }
+
+    #[test]
+    fn test_parse_compdb_no_command() {
+        let json = r#"[{"directory": "/build", "file": "foo.c"}]"#;
+        let commands = parse(json.as_bytes()).unwrap();
+        assert!(commands[0].args().is_err());
+    }
+
+    #[test]
+    fn test_create_graph_skip_invalid() {
+        let json = r#"[
+            {"directory": "/build", "file": "foo.c"},
+            {"directory": "/build", "file": "bar.c", "command": "cc -c bar.c"}
+        ]"#;
+        let commands = parse(json.as_bytes()).unwrap();
+        let graph = create_graph(&CompilerGroup::new(), &commands).unwrap();
+        assert_eq!(graph.node_count(), 1);
+        assert_eq!(graph.raw_nodes()[0].weight.title, "bar.c");
+    }
+}
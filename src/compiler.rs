@@ -3,7 +3,7 @@ use std::collections::hash_map;
 use std::collections::HashMap;
 use std::env;
 use std::fmt::{Display, Formatter};
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Write};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
@@ -15,12 +15,17 @@ use sha2::{Digest, Sha256};
 
 use crate::cache::{Cache, FileHasher};
 use crate::config::Config;
+use crate::io::filecache::{CachedOutput, OutputRole};
+use crate::io::history::TaskHistory;
 use crate::io::memstream::MemStream;
 use crate::io::statistic::Statistic;
 
+const HISTORY_FILE: &str = "history.bin";
+
 #[derive(Debug)]
 pub enum CompilerError {
     InvalidArguments(String),
+    UnknownArguments(Vec<String>),
     ToolchainNotFound(PathBuf),
 }
 
@@ -30,6 +35,9 @@ impl Display for CompilerError {
             CompilerError::InvalidArguments(ref arg) => {
                 write!(f, "can't parse command line arguments: {}", arg)
             }
+            CompilerError::UnknownArguments(ref args) => {
+                write!(f, "found unknown command line arguments: {:?}", args)
+            }
             CompilerError::ToolchainNotFound(ref arg) => {
                 write!(f, "can't find toolchain for: {}", arg.display())
             }
@@ -41,6 +49,7 @@ impl ::std::error::Error for CompilerError {
     fn description(&self) -> &str {
         match self {
             CompilerError::InvalidArguments(_) => "can't parse command line arguments",
+            CompilerError::UnknownArguments(_) => "found unknown command line arguments",
             CompilerError::ToolchainNotFound(_) => "can't find toolchain",
         }
     }
@@ -50,6 +59,12 @@ impl ::std::error::Error for CompilerError {
     }
 }
 
+impl From<String> for CompilerError {
+    fn from(message: String) -> Self {
+        CompilerError::InvalidArguments(message)
+    }
+}
+
 // Scope of command line argument.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Scope {
@@ -68,12 +83,26 @@ pub enum InputKind {
     Source,
     Marker,
     Precompiled,
+    // Compiled module interface (BMI)
+    Module,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum OutputKind {
     Object,
     Marker,
+    // Debug information file (not produced by octobuild compilation)
+    DebugInfo,
+    // Compiled module interface (BMI)
+    Module,
+}
+
+// Compiled module interface reference: "[name=]path".
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleFile {
+    // Module name (None - name is stored in module file)
+    pub name: Option<String>,
+    pub path: PathBuf,
 }
 
 #[derive(Debug, PartialEq)]
@@ -148,6 +177,7 @@ pub struct SharedState {
     pub semaphore: Semaphore,
     pub cache: Cache,
     pub statistic: Statistic,
+    pub history: TaskHistory,
 }
 
 #[derive(Default)]
@@ -160,6 +190,7 @@ impl SharedState {
             semaphore,
             statistic: Statistic::new(),
             cache: Cache::new(&config),
+            history: TaskHistory::new(config.cache_dir.join(HISTORY_FILE), config.cache_readonly),
         })
     }
 
@@ -171,6 +202,28 @@ impl SharedState {
     }
 }
 
+impl ModuleFile {
+    pub fn parse(value: &str) -> Self {
+        match value.find('=') {
+            Some(pos) => ModuleFile {
+                name: Some(value[..pos].to_string()),
+                path: PathBuf::from(&value[pos + 1..]),
+            },
+            None => ModuleFile {
+                name: None,
+                path: PathBuf::from(value),
+            },
+        }
+    }
+
+    pub fn to_arg(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("{}={}", name, self.path.display()),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
 impl CommandEnv {
     pub fn new() -> Self {
         Default::default()
@@ -289,6 +342,31 @@ pub struct OutputInfo {
     pub status: Option<i32>,
     pub stdout: Vec<u8>,
     pub stderr: Vec<u8>,
+    // Not transferred: builder output is always remote for client.
+    #[serde(skip)]
+    pub origin: OutputOrigin,
+    // Name of builder for remote output.
+    #[serde(skip)]
+    pub agent: Option<String>,
+}
+
+// How task output was produced.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum OutputOrigin {
+    #[default]
+    Local,
+    Cache,
+    Remote,
+}
+
+impl OutputOrigin {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputOrigin::Local => "local",
+            OutputOrigin::Cache => "cache",
+            OutputOrigin::Remote => "remote",
+        }
+    }
 }
 
 impl OutputInfo {
@@ -297,6 +375,8 @@ impl OutputInfo {
             status: output.status.code(),
             stdout: output.stdout,
             stderr: output.stderr,
+            origin: OutputOrigin::Local,
+            agent: None,
         }
     }
 
@@ -320,6 +400,12 @@ pub struct CompilationArgs {
     pub output_precompiled: Option<PathBuf>,
     // Marker for precompiled header.
     pub marker_precompiled: Option<String>,
+    // Parallel compilation limit for command source files (/MPn).
+    pub parallel_limit: Option<usize>,
+    // Input compiled module interfaces.
+    pub input_modules: Vec<ModuleFile>,
+    // Output compiled module interface file name.
+    pub output_module: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug)]
@@ -343,6 +429,10 @@ pub struct CompileStep {
     pub output_object: Option<PathBuf>,
     // Output precompiled header file name.
     pub output_precompiled: Option<PathBuf>,
+    // Input compiled module interfaces.
+    pub input_modules: Vec<ModuleFile>,
+    // Output compiled module interface file name.
+    pub output_module: Option<PathBuf>,
     // Preprocessed source file.
     pub preprocessed: MemStream,
 }
@@ -358,6 +448,8 @@ impl CompileStep {
         CompileStep {
             output_object: Some(task.output_object),
             output_precompiled: task.shared.output_precompiled.clone(),
+            input_modules: task.shared.input_modules.clone(),
+            output_module: task.shared.output_module.clone(),
             input_precompiled: if use_precompiled {
                 task.shared.input_precompiled.clone()
             } else {
@@ -383,7 +475,7 @@ pub trait Toolchain: Send + Sync {
         &self,
         command: CommandInfo,
         args: &[String],
-    ) -> Result<Vec<CompilationTask>, String>;
+    ) -> Result<Vec<CompilationTask>, CompilerError>;
     // Preprocessing source file.
     fn preprocess_step(
         &self,
@@ -399,6 +491,14 @@ pub trait Toolchain: Send + Sync {
 
     // Compile preprocessed file.
     fn compile_step(&self, state: &SharedState, task: CompileStep) -> Result<OutputInfo, Error>;
+    // Write relocatable toolchain package (tar archive). Returns false if packaging is not supported.
+    fn create_package(&self, _output: &mut dyn Write) -> Result<bool, Error> {
+        Ok(false)
+    }
+    // Compiler executable included into toolchain package.
+    fn package_binary(&self) -> Option<PathBuf> {
+        None
+    }
     // Compile preprocessed file.
     fn compile_memory(
         &self,
@@ -412,6 +512,8 @@ pub trait Toolchain: Send + Sync {
                     status: output.status,
                     stderr: output.stderr,
                     stdout: Vec::new(),
+                    origin: output.origin,
+                    agent: output.agent,
                 },
                 output.stdout,
             )
@@ -461,14 +563,46 @@ pub trait Toolchain: Send + Sync {
         } else {
             0
         });
+        // Hash input modules and output module flag (keep cache keys without modules unchanged)
+        if !task.input_modules.is_empty() || task.output_module.is_some() {
+            hasher.hash_u64(task.input_modules.len() as u64);
+            for module in task.input_modules.iter() {
+                hasher.hash_bytes(module.name.as_ref().map_or(b"", |name| name.as_bytes()));
+                hasher.hash_bytes(state.cache.file_hash(&module.path)?.hash.as_bytes());
+            }
+            hasher.hash_u8(if task.output_module.is_some() { 1 } else { 0 });
+        }
 
         // Output files list
-        let mut outputs: Vec<PathBuf> = Vec::new();
+        let mut outputs: Vec<CachedOutput> = Vec::new();
         if let Some(ref path) = task.output_object {
-            outputs.push(path.clone());
+            outputs.push(CachedOutput::new(OutputRole::Object, path));
         }
         if let Some(ref path) = task.output_precompiled {
-            outputs.push(path.clone());
+            outputs.push(CachedOutput::new(OutputRole::Precompiled, path));
+        }
+        if let Some(ref path) = task.output_module {
+            outputs.push(CachedOutput::new(OutputRole::Module, path));
+        }
+        // Analysis results (MSVC /analyze) and coverage notes (clang -ftest-coverage) are written
+        // beside object file.
+        if let Some(ref path) = task.output_object {
+            if task
+                .args
+                .iter()
+                .any(|arg| arg.starts_with("/analyze") && arg != "/analyze-")
+            {
+                outputs.push(CachedOutput::optional(
+                    OutputRole::Analysis,
+                    path.with_extension("sarif"),
+                ));
+            }
+            if task.args.iter().any(|arg| arg == "-ftest-coverage") {
+                outputs.push(CachedOutput::optional(
+                    OutputRole::Coverage,
+                    path.with_extension("gcno"),
+                ));
+            }
         }
 
         // Try to get files from cache or run
@@ -509,6 +643,13 @@ impl Compiler for CompilerGroup {
             .flat_map(|c| c.discovery_toolchains())
             .collect()
     }
+    // Load toolchain from unpacked package.
+    fn load_package(&self, identifier: &str, root: &Path) -> Option<Arc<dyn Toolchain>> {
+        self.0
+            .iter()
+            .filter_map(|c| c.load_package(identifier, root))
+            .next()
+    }
 }
 
 trait Hasher: Digest {
@@ -539,6 +680,10 @@ pub trait Compiler: Send + Sync {
     fn resolve_toolchain(&self, command: &CommandInfo) -> Option<Arc<dyn Toolchain>>;
     // Discovery local toolchains.
     fn discovery_toolchains(&self) -> Vec<Arc<dyn Toolchain>>;
+    // Load toolchain from unpacked package.
+    fn load_package(&self, _identifier: &str, _root: &Path) -> Option<Arc<dyn Toolchain>> {
+        None
+    }
 
     #[allow(clippy::type_complexity)]
     fn create_tasks(
@@ -556,9 +701,7 @@ pub trait Compiler: Send + Sync {
             .and_then(|toolchain| {
                 toolchain
                     .create_tasks(command, args)
-                    .map_err(|e| {
-                        Error::new(ErrorKind::InvalidInput, CompilerError::InvalidArguments(e))
-                    })
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
                     .map(|tasks| {
                         tasks
                             .into_iter()
@@ -654,3 +797,24 @@ fn fn_find_exec_native(path: PathBuf) -> Option<PathBuf> {
         _ => None,
     }
 }
+
+// Helpers for compiler argument parsing tests.
+#[cfg(test)]
+pub(crate) mod test {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::{CommandEnv, CommandInfo};
+
+    pub fn test_command(program: &str, current_dir: Option<&str>) -> CommandInfo {
+        CommandInfo {
+            program: PathBuf::from(program),
+            current_dir: current_dir.map(PathBuf::from),
+            env: Arc::new(CommandEnv::new()),
+        }
+    }
+
+    pub fn split_args(line: &str) -> Vec<String> {
+        line.split(' ').map(|x| x.to_string()).collect()
+    }
+}
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::io;
 use std::io::{ErrorKind, Result};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
@@ -8,14 +9,35 @@ use std::{env, fs};
 use yaml_rust::yaml::Hash;
 use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
 
+use crate::io::compression::Compression;
+
 pub struct Config {
-    pub coordinator: Option<reqwest::Url>,
+    // Coordinators in order of preference
+    pub coordinator: Vec<reqwest::Url>,
     pub helper_bind: SocketAddr,
     pub coordinator_bind: SocketAddr,
+    // Other coordinators for builder table synchronization
+    pub coordinator_peers: Vec<reqwest::Url>,
+    // Builder endpoints polled by coordinator directly
+    pub static_builders: Vec<String>,
+    // UDP port for builder discovery on LAN without coordinator
+    pub discovery_port: Option<u16>,
 
     pub process_limit: usize,
+    // Concurrent remote tasks per client on builder
+    pub builder_client_limit: usize,
+    // Run compilers from toolchain packages uploaded by clients (builder side)
+    pub builder_packages: bool,
+    // SHA-256 hashes of toolchain packages the builder is allowed to run
+    pub builder_package_allowlist: Vec<String>,
     pub cache_dir: PathBuf,
     pub cache_limit_mb: u32,
+    // Cache entry compression codec and level
+    pub cache_compression: Compression,
+    // Use cache without writing new entries and cleanup (shared cache consumer).
+    pub cache_readonly: bool,
+    // Replace MSVC /Zi and /ZI with /Z7 to make debug builds cacheable
+    pub msvc_z7: bool,
 }
 
 const CONFIG_FILE_NAME: &str = "octobuild.conf";
@@ -28,9 +50,19 @@ const DEFAULT_CACHE_DIR: &str = "~/.cache/octobuild";
 const PARAM_HELPER_BIND: &str = "helper_bind";
 const PARAM_COORDINATOR_BIND: &str = "coordinator_bind";
 const PARAM_COORDINATOR: &str = "coordinator";
+const PARAM_COORDINATOR_PEERS: &str = "coordinator_peers";
+const PARAM_STATIC_BUILDERS: &str = "static_builders";
+const PARAM_DISCOVERY_PORT: &str = "discovery_port";
 const PARAM_CACHE_LIMIT: &str = "cache_limit_mb";
 const PARAM_CACHE_PATH: &str = "cache_path";
+const PARAM_CACHE_COMPRESSION: &str = "cache_compression";
+const PARAM_CACHE_COMPRESSION_LEVEL: &str = "cache_compression_level";
+const PARAM_CACHE_READONLY: &str = "cache_readonly";
 const PARAM_PROCESS_LIMIT: &str = "process_limit";
+const PARAM_BUILDER_CLIENT_LIMIT: &str = "builder_client_limit";
+const PARAM_BUILDER_PACKAGES: &str = "builder_packages";
+const PARAM_BUILDER_PACKAGE_ALLOWLIST: &str = "builder_package_allowlist";
+const PARAM_MSVC_Z7: &str = "msvc_z7";
 
 impl Config {
     pub fn new() -> Result<Self> {
@@ -40,13 +72,20 @@ impl Config {
     }
 
     pub fn get_coordinator_addrs(&self) -> Result<Vec<SocketAddr>> {
-        match self.coordinator {
-            Some(ref url) => url.socket_addrs(|| match url.scheme() {
+        let mut addrs = Vec::new();
+        for url in self.coordinator.iter() {
+            addrs.append(&mut url.socket_addrs(|| match url.scheme() {
                 "http" => Some(80),
                 _ => None,
-            }),
-            None => Ok(Vec::new()),
+            })?);
         }
+        Ok(addrs)
+    }
+
+    // Broadcast address for builder discovery beacons.
+    pub fn get_discovery_broadcast(&self) -> Option<SocketAddr> {
+        self.discovery_port
+            .map(|port| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, port)))
     }
 
     pub fn defaults() -> Result<Self> {
@@ -71,24 +110,66 @@ impl Config {
             })
         })
         .unwrap_or_else(|| DEFAULT_CACHE_DIR.to_string());
+        let cache_compression_level =
+            get_config(local, global, PARAM_CACHE_COMPRESSION_LEVEL, |v| v.as_i64());
+        let cache_compression = match (
+            get_config(local, global, PARAM_CACHE_COMPRESSION, |v| {
+                v.as_str().map(|v| v.to_string())
+            }),
+            cache_compression_level,
+        ) {
+            (Some(name), level) => Compression::parse(&name, level)?,
+            (None, Some(level)) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Cache compression level {} requires {}",
+                        level, PARAM_CACHE_COMPRESSION
+                    ),
+                ));
+            }
+            (None, None) => Compression::default(),
+        };
+        let cache_readonly =
+            get_config(local, global, PARAM_CACHE_READONLY, |v| v.as_bool()).unwrap_or(false);
         let process_limit = get_config(local, global, PARAM_PROCESS_LIMIT, |v| {
             v.as_i64().map(|v| v as usize)
         })
         .unwrap_or_else(num_cpus::get);
-        let coordinator = get_config(local, global, PARAM_COORDINATOR, |v| {
-            if v.is_null() {
-                None
-            } else {
-                v.as_str().and_then(|v| {
-                    reqwest::Url::parse(v)
-                        .map(|mut v| {
-                            v.set_path("");
-                            v
-                        })
-                        .ok()
-                })
-            }
-        });
+        // By default single client can use whole builder.
+        let builder_client_limit = get_config(local, global, PARAM_BUILDER_CLIENT_LIMIT, |v| {
+            v.as_i64().map(|v| v as usize)
+        })
+        .unwrap_or(process_limit)
+        .max(1);
+        // Uploaded packages contain executables: disabled unless explicitly allowed.
+        let builder_packages =
+            get_config(local, global, PARAM_BUILDER_PACKAGES, |v| v.as_bool()).unwrap_or(false);
+        let builder_package_allowlist =
+            get_config(local, global, PARAM_BUILDER_PACKAGE_ALLOWLIST, |v| {
+                parse_list(v, |v| v.as_str().map(|v| v.to_lowercase()))
+            })
+            .unwrap_or_else(Vec::new);
+        let coordinator =
+            get_config(local, global, PARAM_COORDINATOR, parse_urls).unwrap_or_else(Vec::new);
+        let coordinator_peers =
+            get_config(local, global, PARAM_COORDINATOR_PEERS, parse_urls).unwrap_or_else(Vec::new);
+        let static_builders = get_config(local, global, PARAM_STATIC_BUILDERS, |v| {
+            parse_list(v, |v| v.as_str().map(|v| v.to_string()))
+        })
+        .unwrap_or_else(Vec::new);
+        let discovery_port = match get_config(local, global, PARAM_DISCOVERY_PORT, |v| v.as_i64()) {
+            Some(port) => match u16::try_from(port) {
+                Ok(port) if port > 0 => Some(port),
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid discovery port: {}", port),
+                    ));
+                }
+            },
+            None => None,
+        };
         let helper_bind = get_config(local, global, PARAM_HELPER_BIND, |v| {
             v.as_str().and_then(|v| FromStr::from_str(v).ok())
         })
@@ -97,14 +178,24 @@ impl Config {
             v.as_str().and_then(|v| FromStr::from_str(v).ok())
         })
         .unwrap_or_else(|| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 3000)));
+        let msvc_z7 = get_config(local, global, PARAM_MSVC_Z7, |v| v.as_bool()).unwrap_or(false);
 
         Ok(Config {
             process_limit,
+            builder_client_limit,
+            builder_packages,
+            builder_package_allowlist,
             cache_dir: replace_home(&cache_path)?,
             cache_limit_mb,
+            cache_compression,
+            cache_readonly,
             coordinator,
             helper_bind,
             coordinator_bind,
+            coordinator_peers,
+            static_builders,
+            discovery_port,
+            msvc_z7,
         })
     }
 
@@ -116,6 +207,18 @@ impl Config {
             Yaml::String(PARAM_PROCESS_LIMIT.to_string()),
             Yaml::Integer(self.process_limit as i64),
         );
+        y.insert(
+            Yaml::String(PARAM_BUILDER_CLIENT_LIMIT.to_string()),
+            Yaml::Integer(self.builder_client_limit as i64),
+        );
+        y.insert(
+            Yaml::String(PARAM_BUILDER_PACKAGES.to_string()),
+            Yaml::Boolean(self.builder_packages),
+        );
+        y.insert(
+            Yaml::String(PARAM_BUILDER_PACKAGE_ALLOWLIST.to_string()),
+            show_list(&self.builder_package_allowlist, |v| v.as_str()),
+        );
         y.insert(
             Yaml::String(PARAM_CACHE_LIMIT.to_string()),
             Yaml::Integer(i64::from(self.cache_limit_mb)),
@@ -124,11 +227,36 @@ impl Config {
             Yaml::String(PARAM_CACHE_PATH.to_string()),
             Yaml::String(self.cache_dir.to_str().unwrap().to_string()),
         );
+        y.insert(
+            Yaml::String(PARAM_CACHE_COMPRESSION.to_string()),
+            Yaml::String(self.cache_compression.name().to_string()),
+        );
+        y.insert(
+            Yaml::String(PARAM_CACHE_COMPRESSION_LEVEL.to_string()),
+            self.cache_compression
+                .level()
+                .map_or(Yaml::Null, Yaml::Integer),
+        );
+        y.insert(
+            Yaml::String(PARAM_CACHE_READONLY.to_string()),
+            Yaml::Boolean(self.cache_readonly),
+        );
         y.insert(
             Yaml::String(PARAM_COORDINATOR.to_string()),
-            self.coordinator
-                .as_ref()
-                .map_or(Yaml::Null, |v| Yaml::String(v.as_str().to_string())),
+            show_list(&self.coordinator, |v| v.as_str()),
+        );
+        y.insert(
+            Yaml::String(PARAM_COORDINATOR_PEERS.to_string()),
+            show_list(&self.coordinator_peers, |v| v.as_str()),
+        );
+        y.insert(
+            Yaml::String(PARAM_STATIC_BUILDERS.to_string()),
+            show_list(&self.static_builders, |v| v.as_str()),
+        );
+        y.insert(
+            Yaml::String(PARAM_DISCOVERY_PORT.to_string()),
+            self.discovery_port
+                .map_or(Yaml::Null, |v| Yaml::Integer(i64::from(v))),
         );
         y.insert(
             Yaml::String(PARAM_HELPER_BIND.to_string()),
@@ -138,6 +266,10 @@ impl Config {
             Yaml::String(PARAM_COORDINATOR_BIND.to_string()),
             Yaml::String(self.coordinator_bind.to_string()),
         );
+        y.insert(
+            Yaml::String(PARAM_MSVC_Z7.to_string()),
+            Yaml::Boolean(self.msvc_z7),
+        );
         YamlEmitter::new(&mut content).dump(&Yaml::Hash(y)).unwrap();
         println!("{}", content);
     }
@@ -188,6 +320,44 @@ where
         .or_else(|| global.as_ref().and_then(|i| op(&i[param])))
 }
 
+// Parse single value or list of values.
+fn parse_list<F, T>(v: &Yaml, op: F) -> Option<Vec<T>>
+where
+    F: Fn(&Yaml) -> Option<T>,
+{
+    match v {
+        Yaml::Null | Yaml::BadValue => None,
+        Yaml::Array(ref items) => Some(items.iter().filter_map(op).collect()),
+        _ => op(v).map(|v| vec![v]),
+    }
+}
+
+fn parse_urls(v: &Yaml) -> Option<Vec<reqwest::Url>> {
+    parse_list(v, |v| {
+        v.as_str().and_then(|v| {
+            reqwest::Url::parse(v)
+                .map(|mut v| {
+                    v.set_path("");
+                    v
+                })
+                .ok()
+        })
+    })
+}
+
+fn show_list<T, F: Fn(&T) -> &str>(items: &[T], op: F) -> Yaml {
+    match items.len() {
+        0 => Yaml::Null,
+        1 => Yaml::String(op(&items[0]).to_string()),
+        _ => Yaml::Array(
+            items
+                .iter()
+                .map(|v| Yaml::String(op(v).to_string()))
+                .collect(),
+        ),
+    }
+}
+
 fn load_config<P: AsRef<Path>>(path: P) -> Result<Yaml> {
     let content = fs::read_to_string(path)?;
     match YamlLoader::load_from_str(&content) {
@@ -221,3 +391,100 @@ fn replace_home(path: &str) -> Result<PathBuf> {
         Ok(Path::new(path).to_path_buf())
     }
 }
+
+#[test]
+fn test_coordinator_list() {
+    let local =
+        YamlLoader::load_from_str("coordinator: [\"http://a:3000/rpc\", \"http://b:3000\"]")
+            .unwrap()
+            .pop();
+    let global = YamlLoader::load_from_str("coordinator: http://c:3000\nstatic_builders: c:3001")
+        .unwrap()
+        .pop();
+    let config = Config::load(&local, &global, true).unwrap();
+    assert_eq!(
+        config
+            .coordinator
+            .iter()
+            .map(|v| v.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["http://a:3000/", "http://b:3000/"]
+    );
+    assert_eq!(config.static_builders, vec!["c:3001".to_string()]);
+    assert!(config.coordinator_peers.is_empty());
+    assert!(!config.msvc_z7);
+
+    let config = Config::load(&None, &global, true).unwrap();
+    assert_eq!(config.coordinator.len(), 1);
+}
+
+#[test]
+fn test_builder_client_limit() {
+    let local = YamlLoader::load_from_str("process_limit: 8").unwrap().pop();
+    let config = Config::load(&local, &None, true).unwrap();
+    assert_eq!(config.builder_client_limit, 8);
+
+    let local = YamlLoader::load_from_str("process_limit: 8\nbuilder_client_limit: 2")
+        .unwrap()
+        .pop();
+    let config = Config::load(&local, &None, true).unwrap();
+    assert_eq!(config.builder_client_limit, 2);
+}
+
+#[test]
+fn test_builder_packages() {
+    let config = Config::load(&None, &None, true).unwrap();
+    assert!(!config.builder_packages);
+    assert!(config.builder_package_allowlist.is_empty());
+
+    let local =
+        YamlLoader::load_from_str("builder_packages: true\nbuilder_package_allowlist: ABCD")
+            .unwrap()
+            .pop();
+    let config = Config::load(&local, &None, true).unwrap();
+    assert!(config.builder_packages);
+    assert_eq!(config.builder_package_allowlist, vec!["abcd".to_string()]);
+}
+
+#[test]
+fn test_cache_options() {
+    let config = Config::load(&None, &None, true).unwrap();
+    assert_eq!(config.cache_compression, Compression::Lz4(1));
+    assert!(!config.cache_readonly);
+
+    let local = YamlLoader::load_from_str(
+        "cache_compression: zstd\ncache_compression_level: 19\ncache_readonly: true",
+    )
+    .unwrap()
+    .pop();
+    let config = Config::load(&local, &None, true).unwrap();
+    assert_eq!(config.cache_compression, Compression::Zstd(19));
+    assert!(config.cache_readonly);
+
+    let local = YamlLoader::load_from_str("cache_compression: zstd\ncache_compression_level: 30")
+        .unwrap()
+        .pop();
+    assert!(Config::load(&local, &None, true).is_err());
+
+    // Level without codec is ambiguous.
+    let local = YamlLoader::load_from_str("cache_compression_level: 9")
+        .unwrap()
+        .pop();
+    assert!(Config::load(&local, &None, true).is_err());
+}
+
+#[test]
+fn test_discovery_port() {
+    let local = YamlLoader::load_from_str("discovery_port: 3001")
+        .unwrap()
+        .pop();
+    let config = Config::load(&local, &None, true).unwrap();
+    assert_eq!(config.discovery_port, Some(3001));
+
+    for port in &["0", "70000", "-1"] {
+        let local = YamlLoader::load_from_str(&format!("discovery_port: {}", port))
+            .unwrap()
+            .pop();
+        assert!(Config::load(&local, &None, true).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Compression of single cached file with compression level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Lz4(u32),
+    Zstd(i32),
+}
+
+impl Compression {
+    pub fn parse(name: &str, level: Option<i64>) -> Result<Self, Error> {
+        let (compression, range) = match name {
+            "none" => return Ok(Compression::None),
+            "lz4" => (Compression::Lz4(level.unwrap_or(1) as u32), 0..=16),
+            "zstd" => (Compression::Zstd(level.unwrap_or(3) as i32), 1..=22),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown cache compression: {}", name),
+                ));
+            }
+        };
+        match level {
+            Some(level) if !range.contains(&level) => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Invalid {} compression level {}: expected {}..={}",
+                    name,
+                    level,
+                    range.start(),
+                    range.end()
+                ),
+            )),
+            _ => Ok(compression),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Lz4(_) => "lz4",
+            Compression::Zstd(_) => "zstd",
+        }
+    }
+
+    pub fn level(self) -> Option<i64> {
+        match self {
+            Compression::None => None,
+            Compression::Lz4(level) => Some(i64::from(level)),
+            Compression::Zstd(level) => Some(i64::from(level)),
+        }
+    }
+
+    // Codec identifier stored in cache entry (level is not required for decompression).
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4(_) => 1,
+            Compression::Zstd(_) => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4(0)),
+            2 => Some(Compression::Zstd(0)),
+            _ => None,
+        }
+    }
+
+    pub fn compress<R: Read, W: Write>(self, reader: &mut R, writer: W) -> Result<(), Error> {
+        match self {
+            Compression::None => {
+                let mut writer = writer;
+                io::copy(reader, &mut writer)?;
+                Ok(())
+            }
+            Compression::Lz4(level) => {
+                let mut encoder = lz4::EncoderBuilder::new().level(level).build(writer)?;
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().1
+            }
+            Compression::Zstd(level) => {
+                let mut encoder = zstd::Encoder::new(writer, level)?;
+                io::copy(reader, &mut encoder)?;
+                encoder.finish().map(|_| ())
+            }
+        }
+    }
+
+    pub fn decompress<R: Read, W: Write>(self, reader: R, writer: &mut W) -> Result<(), Error> {
+        match self {
+            Compression::None => {
+                let mut reader = reader;
+                io::copy(&mut reader, writer)?;
+            }
+            Compression::Lz4(_) => {
+                io::copy(&mut lz4::Decoder::new(reader)?, writer)?;
+            }
+            Compression::Zstd(_) => {
+                io::copy(&mut zstd::Decoder::new(reader)?, writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Lz4(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Compression;
+
+    #[test]
+    fn test_compression_parse() {
+        assert_eq!(
+            Compression::parse("zstd", Some(9)).unwrap(),
+            Compression::Zstd(9)
+        );
+        assert_eq!(
+            Compression::parse("lz4", None).unwrap(),
+            Compression::Lz4(1)
+        );
+        assert!(Compression::parse("gzip", None).is_err());
+        assert!(Compression::parse("lz4", Some(17)).is_err());
+        assert!(Compression::parse("lz4", Some(-1)).is_err());
+        assert!(Compression::parse("zstd", Some(0)).is_err());
+        assert!(Compression::parse("zstd", Some(23)).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind, Write};
+
+use serde::Serialize;
+
+use crate::compiler::CompilerError;
+
+// Reasons why tasks were executed as is instead of octobuild compilation.
+#[derive(Default)]
+pub struct FallbackStatistic {
+    reasons: HashMap<String, FallbackReason>,
+}
+
+#[derive(Serialize)]
+pub struct FallbackReason {
+    // Unknown command line flag or error message
+    pub reason: String,
+    // Affected task count
+    pub count: usize,
+    // First affected task
+    pub example: String,
+}
+
+impl FallbackStatistic {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Every unknown flag is counted separately.
+    pub fn add(&mut self, title: &str, error: &Error) {
+        let unknown = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<CompilerError>())
+            .and_then(|e| match e {
+                CompilerError::UnknownArguments(ref args) => Some(args),
+                _ => None,
+            });
+        match unknown {
+            Some(args) => {
+                let mut flags: Vec<String> = args
+                    .iter()
+                    .map(|arg| format!("unknown argument: {}", flag_name(arg)))
+                    .collect();
+                flags.sort();
+                flags.dedup();
+                for flag in flags.into_iter() {
+                    self.add_reason(flag, title);
+                }
+            }
+            None => self.add_reason(error.to_string(), title),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reasons.is_empty()
+    }
+
+    // Most frequent reasons first.
+    pub fn reasons(&self) -> Vec<&FallbackReason> {
+        let mut result: Vec<&FallbackReason> = self.reasons.values().collect();
+        result.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.reason.cmp(&b.reason)));
+        result
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_json::to_writer_pretty(writer, &self.reasons())
+            .map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+
+    fn add_reason(&mut self, reason: String, title: &str) {
+        self.reasons
+            .entry(reason.clone())
+            .or_insert_with(|| FallbackReason {
+                reason,
+                count: 0,
+                example: title.to_string(),
+            })
+            .count += 1;
+    }
+}
+
+impl fmt::Display for FallbackStatistic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        writeln!(f, "Fallback reasons:")?;
+        writeln!(f, "{:>8}  reason (example task)", "tasks")?;
+        for item in self.reasons() {
+            writeln!(f, "{:>8}  {} ({})", item.count, item.reason, item.example)?;
+        }
+        Ok(())
+    }
+}
+
+// Flag without value: "/errorReport:prompt" -> "/errorReport:".
+fn flag_name(arg: &str) -> &str {
+    match arg.find(&[':', '='][..]) {
+        Some(pos) => &arg[..=pos],
+        None => arg,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Error, ErrorKind};
+
+    use crate::compiler::CompilerError;
+
+    use super::FallbackStatistic;
+
+    #[test]
+    fn test_fallback_reasons() {
+        let mut statistic = FallbackStatistic::new();
+        let unknown = |args: &[&str]| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                CompilerError::UnknownArguments(args.iter().map(|s| s.to_string()).collect()),
+            )
+        };
+        statistic.add("a.cpp", &unknown(&["/Zi", "/errorReport:prompt"]));
+        statistic.add("b.cpp", &unknown(&["/errorReport:none"]));
+        statistic.add(
+            "link",
+            &Error::new(
+                ErrorKind::InvalidInput,
+                CompilerError::InvalidArguments("Can't find source file path.".to_string()),
+            ),
+        );
+
+        let reasons: Vec<(&str, usize, &str)> = statistic
+            .reasons()
+            .into_iter()
+            .map(|r| (r.reason.as_str(), r.count, r.example.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("unknown argument: /errorReport:", 2, "a.cpp"),
+                (
+                    "can't parse command line arguments: Can't find source file path.",
+                    1,
+                    "link"
+                ),
+                ("unknown argument: /Zi", 1, "a.cpp"),
+            ]
+        );
+
+        let mut json = Vec::new();
+        statistic.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[0]["reason"], "unknown argument: /errorReport:");
+        assert_eq!(json[0]["count"], 2);
+    }
+}
@@ -1,28 +1,64 @@
-use std::cmp::min;
 use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
 use std::fs;
 use std::fs::{File, OpenOptions};
+use std::io;
 use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use super::super::compiler::OutputInfo;
+use fs2::FileExt;
+use log::warn;
+use uuid::Uuid;
+
+use super::super::compiler::{OutputInfo, OutputOrigin};
 use super::super::config::Config;
-use super::super::utils::DEFAULT_BUF_SIZE;
 use super::binary::*;
+use super::compression::Compression;
 use super::counter::Counter;
 use super::statistic::Statistic;
 use std::time::SystemTime;
 
-const HEADER: &[u8] = b"OBCF\x00\x03";
+// Cache entry with separately compressed output files.
+const HEADER: &[u8] = b"OBCF\x00\x04";
+// Cache entry compressed as single lz4 stream (format 3).
+const HEADER_LEGACY: &[u8] = b"OBCF\x00\x03";
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4D, 0x18];
 const FOOTER: &[u8] = b"END\x00";
 const SUFFIX: &str = ".lz4";
+// Prefix of files which are not completely written yet.
+const TEMP_PREFIX: &str = "~tmp~";
+// Lock file for cleanup of cache shared by several processes and hosts.
+const LOCK_FILE: &str = "cleanup.lock";
+// Temporary files older than this age are left by crashed processes.
+const TEMP_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+// Cached output file role.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputRole {
+    Object,
+    Precompiled,
+    Module,
+    DebugInfo,
+    Analysis,
+    Coverage,
+}
+
+// Output file stored in cache entry.
+#[derive(Clone, Debug)]
+pub struct CachedOutput {
+    pub role: OutputRole,
+    pub path: PathBuf,
+    // File can be absent after successful task execution
+    pub optional: bool,
+}
 
 #[derive(Debug)]
 pub enum CacheError {
     InvalidHeader(PathBuf),
     InvalidFooter(PathBuf),
     PackedFilesMismatch(PathBuf),
+    UnknownCompression(PathBuf, u8),
     MutexError(String),
 }
 
@@ -40,6 +76,12 @@ impl Display for CacheError {
                 "unexpected count of packed cached files: {}",
                 path.display()
             ),
+            CacheError::UnknownCompression(ref path, id) => write!(
+                f,
+                "unknown cached file compression {}: {}",
+                id,
+                path.display()
+            ),
             CacheError::MutexError(ref message) => write!(f, "mutex error: {}", message),
         }
     }
@@ -51,6 +93,7 @@ impl ::std::error::Error for CacheError {
             CacheError::InvalidHeader(_) => "invalid cache file header",
             CacheError::InvalidFooter(_) => "invalid cache file footer",
             CacheError::PackedFilesMismatch(_) => "unexpected count of packed cached files",
+            CacheError::UnknownCompression(_, _) => "unknown cached file compression",
             CacheError::MutexError(_) => "mutex error",
         }
     }
@@ -63,6 +106,42 @@ impl ::std::error::Error for CacheError {
 pub struct FileCache {
     cache_dir: PathBuf,
     cache_limit: u64,
+    // Ignore cached results (results are still written to cache).
+    rebuild: bool,
+    // Only read cached results (cache is shared and updated by other hosts).
+    readonly: bool,
+    compression: Compression,
+}
+
+impl OutputRole {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputRole::Object => "object",
+            OutputRole::Precompiled => "precompiled",
+            OutputRole::Module => "module",
+            OutputRole::DebugInfo => "debug",
+            OutputRole::Analysis => "analysis",
+            OutputRole::Coverage => "coverage",
+        }
+    }
+}
+
+impl CachedOutput {
+    pub fn new<P: Into<PathBuf>>(role: OutputRole, path: P) -> Self {
+        CachedOutput {
+            role,
+            path: path.into(),
+            optional: false,
+        }
+    }
+
+    pub fn optional<P: Into<PathBuf>>(role: OutputRole, path: P) -> Self {
+        CachedOutput {
+            role,
+            path: path.into(),
+            optional: true,
+        }
+    }
 }
 
 struct CacheFile {
@@ -76,14 +155,21 @@ impl FileCache {
         FileCache {
             cache_dir: config.cache_dir.clone(),
             cache_limit: u64::from(config.cache_limit_mb) * 1024 * 1024,
+            rebuild: false,
+            readonly: config.cache_readonly,
+            compression: config.cache_compression,
         }
     }
 
+    pub fn set_rebuild(&mut self, rebuild: bool) {
+        self.rebuild = rebuild;
+    }
+
     pub fn run_cached<F: FnOnce() -> Result<OutputInfo, Error>, C: Fn() -> bool>(
         &self,
         statistic: &Statistic,
         hash: &str,
-        outputs: &[PathBuf],
+        outputs: &[CachedOutput],
         worker: F,
         checker: C,
     ) -> Result<OutputInfo, Error> {
@@ -92,18 +178,46 @@ impl FileCache {
             .join(&hash[0..2])
             .join(&(hash[2..].to_string() + SUFFIX));
         // Try to read data from cache.
-        if let Ok(output) = read_cache(statistic, &path, outputs) {
-            return Ok(output);
+        if !self.rebuild {
+            if let Ok(output) = read_cache(statistic, &path, outputs, !self.readonly) {
+                return Ok(output);
+            }
         }
         // Run task and save result to cache.
         let output = worker()?;
-        if checker() {
-            write_cache(statistic, &path, outputs, &output)?;
+        if checker() && !self.readonly {
+            // Cache is only an optimization: task result is valid anyway.
+            if let Err(e) = write_cache(statistic, &path, outputs, &output, self.compression) {
+                warn!("Can't write cache entry {}: {}", path.display(), e);
+            }
         }
         Ok(output)
     }
 
     pub fn cleanup(&self) -> Result<(), Error> {
+        if self.readonly {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.cache_dir)?;
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.cache_dir.join(LOCK_FILE))?;
+        match lock.try_lock_exclusive() {
+            Ok(_) => {}
+            // Cache is already cleaned up by another process.
+            Err(ref e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+        let result = self.cleanup_locked();
+        drop(lock.unlock());
+        result
+    }
+
+    fn cleanup_locked(&self) -> Result<(), Error> {
         let mut files = find_cache_files(&self.cache_dir, Vec::new())?;
         files.sort_by(|a, b| b.accessed.cmp(&a.accessed));
 
@@ -111,7 +225,7 @@ impl FileCache {
         for item in files.into_iter() {
             cache_size += item.size;
             if cache_size > self.cache_limit {
-                fs::remove_file(&item.path)?;
+                remove_cache_file(&item.path)?;
             }
         }
         Ok(())
@@ -122,11 +236,29 @@ fn find_cache_files(dir: &Path, mut files: Vec<CacheFile>) -> Result<Vec<CacheFi
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        let stat = fs::metadata(&path)?;
+        // File can be renamed or removed by another process.
+        let stat = match fs::metadata(&path) {
+            Ok(stat) => stat,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
         if stat.is_dir() {
             let r = find_cache_files(&path, files);
             files = r?;
-        } else {
+            continue;
+        }
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(TEMP_PREFIX) {
+            let expired = stat
+                .modified()?
+                .elapsed()
+                .map(|age| age > TEMP_LIFETIME)
+                .unwrap_or(false);
+            if expired {
+                remove_cache_file(&path)?;
+            }
+        } else if name.ends_with(SUFFIX) {
             files.push(CacheFile {
                 path,
                 size: stat.len(),
@@ -137,38 +269,19 @@ fn find_cache_files(dir: &Path, mut files: Vec<CacheFile>) -> Result<Vec<CacheFi
     Ok(files)
 }
 
-fn write_cached_file<W: Write>(stream: &mut W, path: &PathBuf) -> Result<(), Error> {
-    let mut buf: [u8; DEFAULT_BUF_SIZE] = [0; DEFAULT_BUF_SIZE];
-    let mut file = File::open(path)?;
-    let total_size = file.seek(SeekFrom::End(0))?;
-    file.seek(SeekFrom::Start(0))?;
-    write_u64(stream, total_size)?;
-    let mut need_size = total_size;
-    loop {
-        let size = file.read(&mut buf)?;
-        if size == 0 && need_size == 0 {
-            break;
-        }
-        if size == 0 {
-            return Err(Error::new(
-                ErrorKind::BrokenPipe,
-                "Unexpected end of stream",
-            ));
-        }
-        if need_size < size as u64 {
-            return Err(Error::new(ErrorKind::BrokenPipe, "Expected end of stream"));
-        }
-        stream.write_all(&buf[0..size])?;
-        need_size -= size as u64;
+fn remove_cache_file(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        r => r,
     }
-    Ok(())
 }
 
 fn write_cache(
     statistic: &Statistic,
     path: &Path,
-    paths: &[PathBuf],
+    outputs: &[CachedOutput],
     output: &OutputInfo,
+    compression: Compression,
 ) -> Result<(), Error> {
     if !output.success() {
         return Ok(());
@@ -176,72 +289,200 @@ fn write_cache(
     if let Some(parent) = path.parent() {
         fs::create_dir_all(&parent)?
     }
-    let mut stream = lz4::EncoderBuilder::new()
-        .level(1)
-        .build(Counter::writer(File::create(path)?))?;
-    stream.write_all(HEADER)?;
-    write_usize(&mut stream, paths.len())?;
-    for path in paths.iter() {
-        write_cached_file(&mut stream, path)?;
-    }
-    write_output(&mut stream, output)?;
-    stream.write_all(FOOTER)?;
-    let (writer, result) = stream.finish();
-    statistic.add_miss(writer.len());
-    result
+    // Other processes never see partially written entry.
+    let size = write_atomic(path, |file| write_entry(file, outputs, output, compression))?;
+    statistic.add_miss(size as usize);
+    Ok(())
 }
 
-fn read_cached_file<R: Read>(stream: &mut R, path: &PathBuf) -> Result<(), Error> {
-    let mut buf: [u8; DEFAULT_BUF_SIZE] = [0; DEFAULT_BUF_SIZE];
-    let total_size = read_u64(stream)?;
-    let mut need_size = total_size;
-
-    let mut file = File::create(path)?;
-    file.set_len(total_size as u64)?;
-    while need_size > 0 {
-        let need = min(buf.len() as u64, need_size) as usize;
-        let size = stream.read(&mut buf[0..need])?;
-        if size == 0 {
-            return Err(Error::new(ErrorKind::BrokenPipe, "Expected end of stream"));
+fn write_entry(
+    file: &mut File,
+    outputs: &[CachedOutput],
+    output: &OutputInfo,
+    compression: Compression,
+) -> Result<u64, Error> {
+    file.write_all(HEADER)?;
+    write_usize(file, outputs.len())?;
+    for item in outputs.iter() {
+        write_blob(file, item.role.name().as_bytes())?;
+        match File::open(&item.path) {
+            Ok(mut source) => {
+                write_u8(file, 1)?;
+                write_cached_file(file, &mut source, compression)?;
+            }
+            Err(ref e) if item.optional && e.kind() == ErrorKind::NotFound => {
+                write_u8(file, 0)?;
+            }
+            Err(e) => return Err(e),
         }
-        file.write_all(&buf[0..size])?;
-        need_size -= size as u64;
     }
+    write_output(file, output)?;
+    file.write_all(FOOTER)?;
+    file.seek(SeekFrom::End(0))
+}
+
+// Compressed file: compression, stored size and data.
+fn write_cached_file(
+    file: &mut File,
+    source: &mut File,
+    compression: Compression,
+) -> Result<(), Error> {
+    write_u8(file, compression.id())?;
+    let start = file.seek(SeekFrom::End(0))?;
+    write_u64(file, 0)?;
+    compression.compress(source, &mut *file)?;
+    let end = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(start))?;
+    write_u64(file, end - start - 8)?;
+    file.seek(SeekFrom::Start(end))?;
     Ok(())
 }
 
-fn read_cache(statistic: &Statistic, path: &Path, paths: &[PathBuf]) -> Result<OutputInfo, Error> {
+fn read_cache(
+    statistic: &Statistic,
+    path: &Path,
+    outputs: &[CachedOutput],
+    touch: bool,
+) -> Result<OutputInfo, Error> {
     let mut file = OpenOptions::new()
         .read(true)
-        .write(true)
+        .write(touch)
         .open(Path::new(path))?;
-    file.write_all(&[4])?;
-    file.seek(SeekFrom::Start(0))?;
+    let header = read_exact(&mut file, HEADER.len())?;
+    // Update modification time for cleanup.
+    if touch {
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header[0..1])?;
+    }
+    if header.starts_with(LZ4_MAGIC) {
+        file.seek(SeekFrom::Start(0))?;
+        return read_legacy_cache(statistic, path, file, outputs);
+    }
+    if header != HEADER {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            CacheError::InvalidHeader(path.to_path_buf()),
+        ));
+    }
+    file.seek(SeekFrom::Start(HEADER.len() as u64))?;
+    if read_usize(&mut file)? != outputs.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            CacheError::PackedFilesMismatch(path.to_path_buf()),
+        ));
+    }
+    for item in outputs.iter() {
+        let mismatch = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                CacheError::PackedFilesMismatch(path.to_path_buf()),
+            )
+        };
+        if read_blob(&mut file)? != item.role.name().as_bytes() {
+            return Err(mismatch());
+        }
+        match read_u8(&mut file)? {
+            // Optional file was not produced by task.
+            0 if item.optional => {
+                drop(fs::remove_file(&item.path));
+                continue;
+            }
+            1 => {}
+            _ => return Err(mismatch()),
+        }
+        let id = read_u8(&mut file)?;
+        let compression = Compression::from_id(id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                CacheError::UnknownCompression(path.to_path_buf(), id),
+            )
+        })?;
+        let size = read_u64(&mut file)?;
+        let mut stored = Read::by_ref(&mut file).take(size);
+        restore_file(&item.path, |target| {
+            compression.decompress(&mut stored, target)
+        })?;
+        // Skip unread tail of stored data.
+        let rest = stored.limit() as i64;
+        file.seek(SeekFrom::Current(rest))?;
+    }
+    let output = read_output(&mut file)?;
+    if read_exact(&mut file, FOOTER.len())? != FOOTER {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            CacheError::InvalidFooter(path.to_path_buf()),
+        ));
+    }
+    let mut eof = [0];
+    if file.read(&mut eof)? != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            CacheError::InvalidFooter(path.to_path_buf()),
+        ));
+    }
+    statistic.add_hit(file.metadata()?.len() as usize);
+    Ok(output)
+}
+
+fn restore_file<F: FnOnce(&mut File) -> Result<(), Error>>(
+    path: &Path,
+    writer: F,
+) -> Result<(), Error> {
+    drop(fs::remove_file(path));
+    write_atomic(path, writer)
+}
+
+// Write file through temporary file: broken file is never left at target path.
+fn write_atomic<T, F: FnOnce(&mut File) -> Result<T, Error>>(
+    path: &Path,
+    writer: F,
+) -> Result<T, Error> {
+    // Unique name: same file can be written concurrently by several hosts.
+    let mut temp_name = OsString::from(format!("{}{}~", TEMP_PREFIX, Uuid::new_v4()));
+    temp_name.push(path.file_name().unwrap());
+    let temp = path.with_file_name(temp_name);
+    match File::create(&temp)
+        .and_then(|mut file| writer(&mut file))
+        .and_then(|result| fs::rename(&temp, path).map(|_| result))
+    {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            drop(fs::remove_file(&temp));
+            Err(e)
+        }
+    }
+}
+
+// Format 3: required output files in order without roles.
+fn read_legacy_cache(
+    statistic: &Statistic,
+    path: &Path,
+    file: File,
+    outputs: &[CachedOutput],
+) -> Result<OutputInfo, Error> {
     let mut stream = lz4::Decoder::new(Counter::reader(file))?;
-    if read_exact(&mut stream, HEADER.len())? != HEADER {
+    if read_exact(&mut stream, HEADER_LEGACY.len())? != HEADER_LEGACY {
         return Err(Error::new(
             ErrorKind::InvalidInput,
             CacheError::InvalidHeader(path.to_path_buf()),
         ));
     }
-    if read_usize(&mut stream)? != paths.len() {
+    let required: Vec<&CachedOutput> = outputs.iter().filter(|item| !item.optional).collect();
+    if required.len() != outputs.len() || read_usize(&mut stream)? != required.len() {
         return Err(Error::new(
             ErrorKind::InvalidInput,
             CacheError::PackedFilesMismatch(path.to_path_buf()),
         ));
     }
-    for path in paths.iter() {
-        let mut temp_name = OsString::from("~tmp~");
-        temp_name.push(path.file_name().unwrap());
-        let temp = path.with_file_name(temp_name);
-        drop(fs::remove_file(&path));
-        match read_cached_file(&mut stream, &temp).and_then(|_| fs::rename(&temp, &path)) {
-            Ok(_) => {}
-            Err(e) => {
-                drop(fs::remove_file(&temp));
-                return Err(e);
+    for item in required.into_iter() {
+        let size = read_u64(&mut stream)?;
+        restore_file(&item.path, |target| {
+            let copied = io::copy(&mut Read::by_ref(&mut stream).take(size), target)?;
+            if copied != size {
+                return Err(Error::new(ErrorKind::BrokenPipe, "Expected end of stream"));
             }
-        };
+            Ok(())
+        })?;
     }
     let output = read_output(&mut stream)?;
     if read_exact(&mut stream, FOOTER.len())? != FOOTER {
@@ -285,5 +526,216 @@ fn read_output(stream: &mut dyn Read) -> Result<OutputInfo, Error> {
         status: Some(0),
         stdout,
         stderr,
+        origin: OutputOrigin::Cache,
+        agent: None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+
+    use fs2::FileExt;
+    use tempdir::TempDir;
+
+    use crate::compiler::{OutputInfo, OutputOrigin};
+    use crate::io::binary::{write_u64, write_usize};
+    use crate::io::statistic::Statistic;
+
+    use crate::io::compression::Compression;
+
+    use super::{
+        read_cache, write_cache, write_output, CachedOutput, FileCache, OutputRole, LOCK_FILE,
+    };
+
+    fn success(stdout: &[u8]) -> OutputInfo {
+        OutputInfo {
+            status: Some(0),
+            stdout: stdout.to_vec(),
+            stderr: Vec::new(),
+            origin: OutputOrigin::Local,
+            agent: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_optional_outputs() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let statistic = Statistic::new();
+        let entry = temp.path().join("entry.lz4");
+        let outputs = vec![
+            CachedOutput::new(OutputRole::Object, temp.path().join("sample.o")),
+            CachedOutput::optional(OutputRole::Coverage, temp.path().join("sample.gcno")),
+            CachedOutput::optional(OutputRole::Analysis, temp.path().join("sample.sarif")),
+        ];
+        fs::write(&outputs[0].path, b"object data").unwrap();
+        fs::write(&outputs[2].path, b"analysis data").unwrap();
+        write_cache(
+            &statistic,
+            &entry,
+            &outputs,
+            &success(b"sample.cpp"),
+            Compression::default(),
+        )
+        .unwrap();
+
+        fs::remove_file(&outputs[0].path).unwrap();
+        fs::remove_file(&outputs[2].path).unwrap();
+        fs::write(&outputs[1].path, b"stale").unwrap();
+        let output = read_cache(&statistic, &entry, &outputs, true).unwrap();
+        assert_eq!(output.stdout, b"sample.cpp");
+        assert_eq!(output.origin, OutputOrigin::Cache);
+        assert_eq!(fs::read(&outputs[0].path).unwrap(), b"object data");
+        assert!(!outputs[1].path.exists());
+        assert_eq!(fs::read(&outputs[2].path).unwrap(), b"analysis data");
+
+        // Another output set
+        assert!(read_cache(&statistic, &entry, &outputs[0..1], true).is_err());
+        let renamed = vec![CachedOutput::new(
+            OutputRole::Precompiled,
+            temp.path().join("sample.pch"),
+        )];
+        assert!(read_cache(&statistic, &entry, &renamed, true).is_err());
+    }
+
+    #[test]
+    fn test_cache_compression() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let statistic = Statistic::new();
+        let object = temp.path().join("sample.o");
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let outputs = vec![CachedOutput::new(OutputRole::Object, &object)];
+        // Entries with different codecs are readable with any configuration.
+        for (index, compression) in [
+            Compression::None,
+            Compression::Lz4(1),
+            Compression::Zstd(19),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let entry = temp.path().join(format!("entry{}", index));
+            fs::write(&object, &data).unwrap();
+            write_cache(&statistic, &entry, &outputs, &success(b""), *compression).unwrap();
+            fs::remove_file(&object).unwrap();
+            read_cache(&statistic, &entry, &outputs, true).unwrap();
+            assert_eq!(fs::read(&object).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_cache_shared() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let statistic = Statistic::new();
+        let object = temp.path().join("sample.o");
+        let outputs = vec![CachedOutput::new(OutputRole::Object, &object)];
+        let mut cache = FileCache {
+            cache_dir: temp.path().join("cache"),
+            cache_limit: 0,
+            rebuild: false,
+            readonly: true,
+            compression: Compression::default(),
+        };
+        let run = |cache: &FileCache| {
+            cache
+                .run_cached(
+                    &statistic,
+                    "0123456789",
+                    &outputs,
+                    || {
+                        fs::write(&object, b"object").unwrap();
+                        Ok(success(b""))
+                    },
+                    || true,
+                )
+                .unwrap()
+        };
+        let entry = cache.cache_dir.join("01").join("23456789.lz4");
+
+        // Read-only cache is never modified.
+        run(&cache);
+        assert!(!cache.cache_dir.exists());
+        cache.cleanup().unwrap();
+        assert!(!cache.cache_dir.exists());
+
+        // Entry is written without temporary files left.
+        cache.readonly = false;
+        run(&cache);
+        assert_eq!(fs::read_dir(entry.parent().unwrap()).unwrap().count(), 1);
+        assert_eq!(run(&cache).origin, OutputOrigin::Cache);
+
+        // Cleanup is skipped while another process holds lock.
+        let lock = File::create(cache.cache_dir.join(LOCK_FILE)).unwrap();
+        lock.lock_exclusive().unwrap();
+        cache.cleanup().unwrap();
+        assert!(entry.exists());
+        lock.unlock().unwrap();
+        cache.cleanup().unwrap();
+        assert!(!entry.exists());
+    }
+
+    #[test]
+    fn test_cache_write_error() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let statistic = Statistic::new();
+        let object = temp.path().join("sample.o");
+        let outputs = vec![CachedOutput::new(OutputRole::Object, &object)];
+        // Cache directory can't be created over regular file.
+        fs::write(temp.path().join("cache"), b"").unwrap();
+        let cache = FileCache {
+            cache_dir: temp.path().join("cache"),
+            cache_limit: 0,
+            rebuild: false,
+            readonly: false,
+            compression: Compression::default(),
+        };
+        let output = cache
+            .run_cached(
+                &statistic,
+                "0123456789",
+                &outputs,
+                || {
+                    fs::write(&object, b"object").unwrap();
+                    Ok(success(b"compiled"))
+                },
+                || true,
+            )
+            .unwrap();
+        assert_eq!(output.stdout, b"compiled");
+        assert_eq!(fs::read(&object).unwrap(), b"object");
+    }
+
+    #[test]
+    fn test_cache_legacy_format() {
+        let temp = TempDir::new("octobuild").unwrap();
+        let statistic = Statistic::new();
+        let entry = temp.path().join("entry.lz4");
+        let mut stream = lz4::EncoderBuilder::new()
+            .build(fs::File::create(&entry).unwrap())
+            .unwrap();
+        stream.write_all(b"OBCF\x00\x03").unwrap();
+        write_usize(&mut stream, 1).unwrap();
+        write_u64(&mut stream, 6).unwrap();
+        stream.write_all(b"object").unwrap();
+        write_output(&mut stream, &success(b"legacy")).unwrap();
+        stream.write_all(b"END\x00").unwrap();
+        stream.finish().1.unwrap();
+
+        let outputs = vec![CachedOutput::new(
+            OutputRole::Object,
+            temp.path().join("sample.o"),
+        )];
+        let output = read_cache(&statistic, &entry, &outputs, true).unwrap();
+        assert_eq!(output.stdout, b"legacy");
+        assert_eq!(fs::read(&outputs[0].path).unwrap(), b"object");
+
+        // Legacy entries have no optional outputs.
+        let optional = vec![
+            outputs[0].clone(),
+            CachedOutput::optional(OutputRole::Coverage, temp.path().join("sample.gcno")),
+        ];
+        assert!(read_cache(&statistic, &entry, &optional, true).is_err());
+    }
+}
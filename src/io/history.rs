@@ -0,0 +1,119 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::io::tempfile::TempFile;
+
+// Maximum stored task count: tasks not executed by last build are dropped above this limit.
+const HISTORY_LIMIT: usize = 0x10000;
+
+// Task durations from previous builds (milliseconds by task key).
+pub struct TaskHistory {
+    path: PathBuf,
+    // History of shared cache consumer is not saved.
+    readonly: bool,
+    state: Mutex<Option<HistoryState>>,
+}
+
+#[derive(Default)]
+struct HistoryState {
+    durations: HashMap<String, u64>,
+    updated: HashSet<String>,
+}
+
+impl TaskHistory {
+    pub fn new(path: PathBuf, readonly: bool) -> Self {
+        TaskHistory {
+            path,
+            readonly,
+            state: Mutex::new(None),
+        }
+    }
+
+    pub fn duration(&self, key: &str) -> Option<Duration> {
+        self.with_state(|state| state.durations.get(key).map(|v| Duration::from_millis(*v)))
+    }
+
+    pub fn update(&self, key: &str, duration: Duration) {
+        self.with_state(|state| {
+            state
+                .durations
+                .insert(key.to_string(), duration.as_millis() as u64);
+            state.updated.insert(key.to_string());
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if self.readonly {
+            return Ok(());
+        }
+        let path = &self.path;
+        self.with_state(|state| {
+            if state.updated.is_empty() {
+                return Ok(());
+            }
+            if state.durations.len() > HISTORY_LIMIT {
+                let updated = &state.updated;
+                state.durations.retain(|k, _| updated.contains(k));
+            }
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            fs::create_dir_all(dir)?;
+            // Unique name: history can be saved by several processes at once.
+            let temp = TempFile::new_in(dir, ".tmp");
+            bincode::serialize_into(BufWriter::new(File::create(temp.path())?), &state.durations)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            fs::rename(temp.path(), path)?;
+            state.updated.clear();
+            Ok(())
+        })
+    }
+
+    fn with_state<T, F: FnOnce(&mut HistoryState) -> T>(&self, func: F) -> T {
+        let mut holder = self.state.lock().unwrap();
+        let path = &self.path;
+        func(holder.get_or_insert_with(|| {
+            HistoryState {
+                durations: File::open(path)
+                    .ok()
+                    .and_then(|file| bincode::deserialize_from(BufReader::new(file)).ok())
+                    .unwrap_or_default(),
+                updated: HashSet::new(),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tempdir::TempDir;
+
+    use super::TaskHistory;
+
+    #[test]
+    fn test_history_roundtrip() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let path = temp_dir.path().join("history");
+        {
+            let history = TaskHistory::new(path.clone(), false);
+            assert_eq!(history.duration("foo"), None);
+            history.update("foo", Duration::from_millis(1500));
+            history.save().unwrap();
+        }
+        {
+            // Readonly history is loaded but not saved.
+            let history = TaskHistory::new(path.clone(), true);
+            assert_eq!(history.duration("foo"), Some(Duration::from_millis(1500)));
+            history.update("foo", Duration::from_millis(500));
+            history.save().unwrap();
+        }
+        let history = TaskHistory::new(path, false);
+        assert_eq!(history.duration("foo"), Some(Duration::from_millis(1500)));
+        assert_eq!(history.duration("bar"), None);
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::worker::BuildResult;
+
+// Task timings in Chrome trace event format (chrome://tracing, Perfetto).
+pub struct BuildTrace {
+    start: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+// Complete event ("ph": "X") with microsecond timestamps.
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: usize,
+    args: TraceArgs,
+}
+
+#[derive(Serialize)]
+struct TraceArgs {
+    origin: &'static str,
+    status: String,
+}
+
+impl BuildTrace {
+    pub fn new() -> Self {
+        BuildTrace {
+            start: Instant::now(),
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, result: &BuildResult) {
+        let (origin, status) = match result.result {
+            Ok(ref output) => (
+                output.origin.name(),
+                match output.status {
+                    Some(code) => code.to_string(),
+                    None => "terminated".to_string(),
+                },
+            ),
+            Err(ref e) => ("error", e.to_string()),
+        };
+        let event = TraceEvent {
+            name: result.task.title.clone(),
+            cat: origin,
+            ph: "X",
+            ts: micros(result.start.saturating_duration_since(self.start)),
+            dur: micros(result.duration),
+            pid: 1,
+            tid: result.worker,
+            args: TraceArgs { origin, status },
+        };
+        self.events.lock().unwrap().push(event);
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let events = self.events.lock().unwrap();
+        serde_json::to_writer_pretty(
+            writer,
+            &TraceFile {
+                trace_events: &events,
+                display_time_unit: "ms",
+            },
+        )
+        .map_err(|e| Error::new(ErrorKind::Other, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+impl Default for BuildTrace {
+    fn default() -> Self {
+        BuildTrace::new()
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::compiler::{OutputInfo, OutputOrigin};
+    use crate::worker::{BuildAction, BuildResult, BuildTask, TaskOptions};
+
+    use super::BuildTrace;
+
+    #[test]
+    fn test_trace_write() {
+        let trace = BuildTrace::new();
+        let task = Arc::new(BuildTask {
+            title: "foo \"bar\"".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        });
+        let result = Ok(OutputInfo {
+            status: Some(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            origin: OutputOrigin::Cache,
+            agent: None,
+        });
+        trace.record(&BuildResult {
+            task: &task,
+            worker: 3,
+            result: &result,
+            start: trace.start + Duration::from_millis(5),
+            duration: Duration::from_millis(20),
+            completed: 1,
+            total: 1,
+        });
+
+        let mut buffer = Vec::new();
+        trace.write(&mut buffer).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        let event = &json["traceEvents"][0];
+        assert_eq!(event["name"], "foo \"bar\"");
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["ts"], 5000);
+        assert_eq!(event["dur"], 20000);
+        assert_eq!(event["tid"], 3);
+        assert_eq!(event["args"]["origin"], "cache");
+        assert_eq!(event["args"]["status"], "0");
+    }
+}
@@ -14,6 +14,12 @@ impl<T: Clone> Default for Lazy<T> {
 }
 
 impl<T: Clone> Lazy<T> {
+    pub fn with_value(value: T) -> Self {
+        Lazy {
+            holder: RwLock::new(Some(value)),
+        }
+    }
+
     pub fn get<F: FnOnce() -> T>(&self, factory: F) -> T {
         {
             let read_lock = self.holder.read().unwrap();
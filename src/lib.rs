@@ -4,6 +4,11 @@ pub mod cluster {
     pub mod builder;
     pub mod client;
     pub mod common;
+    pub mod discovery;
+}
+
+pub mod compdb {
+    pub mod parser;
 }
 
 pub mod compiler;
@@ -14,12 +19,16 @@ pub mod version;
 
 pub mod io {
     pub mod binary;
+    pub mod compression;
     pub mod counter;
+    pub mod fallback;
     pub mod filecache;
+    pub mod history;
     pub mod memcache;
     pub mod memstream;
     pub mod statistic;
     pub mod tempfile;
+    pub mod trace;
 }
 
 pub mod xg {
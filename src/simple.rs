@@ -1,9 +1,11 @@
+use std::cmp::min;
 use std::env;
 use std::io;
 use std::io::{Error, Write};
 use std::iter::FromIterator;
 use std::path::Path;
-use std::sync::Arc;
+use std::ptr;
+use std::sync::{Arc, Mutex};
 
 use log::error;
 use petgraph::Graph;
@@ -17,9 +19,9 @@ use crate::vs::compiler::VsCompiler;
 use crate::worker::execute_graph;
 use crate::worker::{BuildAction, BuildGraph, BuildResult, BuildTask};
 
-pub fn supported_compilers(temp_dir: &Arc<TempDir>) -> CompilerGroup {
+pub fn supported_compilers(config: &Config, temp_dir: &Arc<TempDir>) -> CompilerGroup {
     CompilerGroup::new()
-        .add(VsCompiler::new(&temp_dir))
+        .add(VsCompiler::new(temp_dir).with_z7(config.msvc_z7))
         .add(ClangCompiler::new())
 }
 
@@ -73,28 +75,94 @@ where
 {
     let args = Vec::from_iter(env::args());
     let command_info = CommandInfo::simple(Path::new(exec));
-    let remote = RemoteCompiler::new(&config.coordinator, compiler);
+    let remote = RemoteCompiler::new(config, compiler);
     let actions = BuildAction::create_tasks(&remote, command_info, &args[1..], exec);
+    let process_limit = actions
+        .iter()
+        .filter_map(|action| match action {
+            BuildAction::Compilation(_, ref task) => task.shared.parallel_limit,
+            _ => None,
+        })
+        .min()
+        .map_or(config.process_limit, |limit| {
+            min(limit, config.process_limit)
+        });
 
     let mut build_graph: BuildGraph = Graph::new();
     for action in actions.into_iter() {
         build_graph.add_node(Arc::new(BuildTask {
             title: action.title().into_owned(),
             action,
+            options: Default::default(),
         }));
     }
-    let result = execute_graph(state, build_graph, config.process_limit, print_task_result);
+    let output = OrderedOutput::new(
+        build_graph
+            .raw_nodes()
+            .iter()
+            .map(|node| node.weight.clone())
+            .collect(),
+    );
+    let result = execute_graph(state, build_graph, process_limit, false, |result| {
+        output.add(result)
+    });
+    output.finish()?;
     println!("{}", state.statistic.to_string());
     result
 }
 
-fn print_task_result(result: BuildResult) -> Result<(), Error> {
-    match result.result {
-        Ok(ref output) => {
-            io::stdout().write_all(&output.stdout)?;
-            io::stderr().write_all(&output.stderr)?;
+// Compiler output is printed in source files order like cl.exe does.
+struct OrderedOutput {
+    tasks: Vec<Arc<BuildTask>>,
+    state: Mutex<OrderedOutputState>,
+}
+
+struct OrderedOutputState {
+    // First not printed task
+    next: usize,
+    // Completed task output: stdout and stderr
+    outputs: Vec<Option<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl OrderedOutput {
+    fn new(tasks: Vec<Arc<BuildTask>>) -> Self {
+        OrderedOutput {
+            state: Mutex::new(OrderedOutputState {
+                next: 0,
+                outputs: tasks.iter().map(|_| None).collect(),
+            }),
+            tasks,
+        }
+    }
+
+    fn add(&self, result: BuildResult) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = self.tasks.iter().position(|t| ptr::eq(&**t, result.task)) {
+            state.outputs[index] = Some(match result.result {
+                Ok(ref output) => (output.stdout.clone(), output.stderr.clone()),
+                Err(_) => (Vec::new(), Vec::new()),
+            });
+        }
+        OrderedOutput::flush(&mut state, false)
+    }
+
+    // Print output of completed tasks after build stop.
+    fn finish(&self) -> Result<(), Error> {
+        OrderedOutput::flush(&mut self.state.lock().unwrap(), true)
+    }
+
+    fn flush(state: &mut OrderedOutputState, all: bool) -> Result<(), Error> {
+        while state.next < state.outputs.len() {
+            match state.outputs[state.next].take() {
+                Some((stdout, stderr)) => {
+                    io::stdout().write_all(&stdout)?;
+                    io::stderr().write_all(&stderr)?;
+                }
+                None if all => {}
+                None => break,
+            }
+            state.next += 1;
         }
-        Err(_) => {}
+        Ok(())
     }
-    Ok(())
 }
@@ -20,6 +20,8 @@ use super::postprocess;
 pub struct VsCompiler {
     temp_dir: Arc<TempDir>,
     toolchains: ToolchainHolder,
+    // Replace /Zi and /ZI with /Z7
+    z7: bool,
 }
 
 impl VsCompiler {
@@ -30,22 +32,30 @@ impl VsCompiler {
         VsCompiler {
             temp_dir: temp_dir.clone(),
             toolchains: ToolchainHolder::new(),
+            z7: false,
         }
     }
+
+    pub fn with_z7(mut self, z7: bool) -> Self {
+        self.z7 = z7;
+        self
+    }
 }
 
 struct VsToolchain {
     temp_dir: Arc<TempDir>,
     path: PathBuf,
     identifier: Lazy<Option<String>>,
+    z7: bool,
 }
 
 impl VsToolchain {
-    pub fn new(path: PathBuf, temp_dir: &Arc<TempDir>) -> Self {
+    pub fn new(path: PathBuf, temp_dir: &Arc<TempDir>, z7: bool) -> Self {
         VsToolchain {
             temp_dir: temp_dir.clone(),
             path,
             identifier: Default::default(),
+            z7,
         }
     }
 }
@@ -61,7 +71,7 @@ impl Compiler for VsCompiler {
         {
             command.find_executable().and_then(|path| {
                 self.toolchains.resolve(&path, |path| {
-                    Arc::new(VsToolchain::new(path, &self.temp_dir))
+                    Arc::new(VsToolchain::new(path, &self.temp_dir, self.z7))
                 })
             })
         } else {
@@ -115,7 +125,9 @@ impl Compiler for VsCompiler {
             .map(|path| -> Vec<PathBuf> { CL_BIN.iter().map(|bin| path.join(bin)).collect() })
             .flat_map(|paths| paths.into_iter())
             .filter(|cl| cl.exists())
-            .map(|cl| -> Arc<dyn Toolchain> { Arc::new(VsToolchain::new(cl, &self.temp_dir)) })
+            .map(|cl| -> Arc<dyn Toolchain> {
+                Arc::new(VsToolchain::new(cl, &self.temp_dir, self.z7))
+            })
             .filter(|toolchain| toolchain.identifier().is_some())
             .collect()
     }
@@ -130,8 +142,8 @@ impl Toolchain for VsToolchain {
         &self,
         command: CommandInfo,
         args: &[String],
-    ) -> Result<Vec<CompilationTask>, String> {
-        super::prepare::create_tasks(command, args)
+    ) -> Result<Vec<CompilationTask>, CompilerError> {
+        super::prepare::create_tasks(command, args, self.z7)
     }
 
     fn preprocess_step(
@@ -192,6 +204,8 @@ impl Toolchain for VsToolchain {
                 status: output.status.code(),
                 stdout: Vec::new(),
                 stderr: output.stderr,
+                origin: OutputOrigin::Local,
+                agent: None,
             }))
         }
     }
@@ -286,12 +300,23 @@ impl Toolchain for VsToolchain {
             }
             None => {}
         }
+        // Compiled module interfaces.
+        for module in task.input_modules.iter() {
+            assert!(module.path.is_absolute());
+            command.arg("/reference").arg(module.to_arg());
+        }
+        if let Some(ref path) = task.output_module {
+            assert!(path.is_absolute());
+            command.arg("/ifcOutput").arg(path);
+        }
         // Execute.
         state.wrap_slow(|| {
             command.output().map(|o| OutputInfo {
                 status: o.status.code(),
                 stdout: prepare_output(temp_file, o.stdout.clone(), o.status.code() == Some(0)),
                 stderr: o.stderr,
+                origin: OutputOrigin::Local,
+                agent: None,
             })
         })
     }
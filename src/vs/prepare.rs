@@ -7,7 +7,8 @@ use local_encoding::{Encoder, Encoding};
 
 use crate::cmd;
 use crate::compiler::{
-    Arg, CommandInfo, CompilationArgs, CompilationTask, InputKind, OutputKind, Scope,
+    Arg, CommandInfo, CompilationArgs, CompilationTask, CompilerError, InputKind, ModuleFile,
+    OutputKind, Scope,
 };
 use std::fs;
 
@@ -17,11 +18,16 @@ enum ParamValue<T> {
     Many(Vec<T>),
 }
 
-pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<CompilationTask>, String> {
+pub fn create_tasks(
+    command: CommandInfo,
+    args: &[String],
+    z7: bool,
+) -> Result<Vec<CompilationTask>, CompilerError> {
     load_arguments(&command.current_dir, args.iter())
-        .map_err(|e: Error| format!("IO error: {}", e))
+        .map_err(|e: Error| CompilerError::InvalidArguments(format!("IO error: {}", e)))
         .and_then(|a| parse_arguments(a.iter()))
-        .and_then(|parsed_args| {
+        .and_then(|a| debug_info_arguments(a, z7))
+        .and_then(|mut parsed_args| {
             // Source file name.
             let input_sources: Vec<PathBuf> = parsed_args
                 .iter()
@@ -33,7 +39,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                 })
                 .collect();
             if input_sources.is_empty() {
-                return Err("Can't find source file path.".to_string());
+                return Err("Can't find source file path.".to_string().into());
             }
             // Precompiled header file name.
             let precompiled_file = match find_param(&parsed_args, |arg: &Arg| -> Option<PathBuf> {
@@ -47,7 +53,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                 ParamValue::None => None,
                 ParamValue::Single(v) => Some(v),
                 ParamValue::Many(v) => {
-                    return Err(format!("Found too many precompiled header files: {:?}", v));
+                    return Err(format!("Found too many precompiled header files: {:?}", v).into());
                 }
             };
             let cwd = command.current_dir.clone();
@@ -89,7 +95,8 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                     return Err(format!(
                         "Found too many precompiled header markers: {}",
                         v.iter().map(|item| item.1.clone()).collect::<String>()
-                    ));
+                    )
+                    .into());
                 }
             };
             // Output object file name.
@@ -105,7 +112,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                     ParamValue::None => None,
                     ParamValue::Single(v) => Some(v),
                     ParamValue::Many(v) => {
-                        return Err(format!("Found too many output object files: {:?}", v));
+                        return Err(format!("Found too many output object files: {:?}", v).into());
                     }
                 }
                 .map(|path| cwd.as_ref().map(|cwd| cwd.join(&path)).unwrap_or(path));
@@ -124,14 +131,91 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                     ParamValue::None => None,
                     ParamValue::Single(v) => Some(v),
                     ParamValue::Many(v) => {
-                        return Err(format!("Found too many output object files: {:?}", v));
+                        return Err(format!("Found too many output object files: {:?}", v).into());
                     }
                 };
+            // Compiled module interfaces.
+            let input_modules: Vec<ModuleFile> = parsed_args
+                .iter()
+                .filter_map(|arg| match arg {
+                    Arg::Input {
+                        ref kind, ref file, ..
+                    } if *kind == InputKind::Module => Some(ModuleFile::parse(file)),
+                    _ => None,
+                })
+                .map(|module| ModuleFile {
+                    path: command.current_dir_join(&module.path),
+                    ..module
+                })
+                .collect();
+            let ifc_output = match find_param(&parsed_args, |arg: &Arg| -> Option<PathBuf> {
+                match *arg {
+                    Arg::Output {
+                        ref kind, ref file, ..
+                    } if *kind == OutputKind::Module => Some(Path::new(file).to_path_buf()),
+                    _ => None,
+                }
+            }) {
+                ParamValue::None => None,
+                ParamValue::Single(v) => Some(v),
+                ParamValue::Many(v) => {
+                    return Err(format!("Found too many module output files: {:?}", v).into());
+                }
+            };
+            // Module interface unit (.ixx is compiled as interface by default).
+            let has_interface_flag = parsed_args.iter().any(|arg| match arg {
+                Arg::Flag { ref flag, .. } => flag == "interface" || flag == "internalPartition",
+                _ => false,
+            });
+            let is_interface_source = input_sources.iter().any(|source| {
+                match source.extension().and_then(|ext| ext.to_str()) {
+                    Some(ext) => ext.eq_ignore_ascii_case("ixx"),
+                    None => false,
+                }
+            });
+            let output_module = if has_interface_flag || is_interface_source {
+                if input_sources.len() > 1 {
+                    return Err("Cannot compile multiple module interface units at once"
+                        .to_string()
+                        .into());
+                }
+                match ifc_output {
+                    Some(ref path) if !path.is_dir() && !path.to_string_lossy().ends_with('\\') => {
+                        Some(command.current_dir_join(path))
+                    }
+                    _ => {
+                        return Err("Can't find module interface output file (/ifcOutput)"
+                            .to_string()
+                            .into());
+                    }
+                }
+            } else {
+                None
+            };
+            if is_interface_source && !has_interface_flag {
+                // Preprocessed file has no .ixx extension.
+                parsed_args.push(Arg::flag(Scope::Compiler, "interface"));
+            }
+            // Parallel compilation limit (/MP without value uses all processors).
+            let parallel_limit = parsed_args
+                .iter()
+                .rev()
+                .filter_map(|arg| match arg {
+                    Arg::Flag { ref flag, .. } if flag.starts_with("MP") => {
+                        flag[2..].parse::<usize>().ok()
+                    }
+                    _ => None,
+                })
+                .next()
+                .map(|limit| limit.max(1));
             let shared = Arc::new(CompilationArgs {
                 args: parsed_args,
                 input_precompiled: input_precompiled.map(|path| command.current_dir_join(&path)),
                 output_precompiled: output_precompiled.map(|path| command.current_dir_join(&path)),
                 marker_precompiled,
+                parallel_limit,
+                input_modules,
+                output_module,
                 command,
             });
             input_sources
@@ -149,6 +233,7 @@ pub fn create_tasks(command: CommandInfo, args: &[String]) -> Result<Vec<Compila
                                         .and_then(|ext| match ext.to_str() {
                                             Some(e) if e.eq_ignore_ascii_case("cpp") => Some("P"),
                                             Some(e) if e.eq_ignore_ascii_case("c") => Some("C"),
+                                            Some(e) if e.eq_ignore_ascii_case("ixx") => Some("P"),
                                             _ => None,
                                         })
                                         .map(|ext| ext.to_string())
@@ -193,6 +278,27 @@ fn get_output_object(
     )
 }
 
+// Debug information in shared PDB (/Zi, /ZI) is written by mspdbsrv outside of object file:
+// it can't be cached or compiled remotely. Embedded debug information (/Z7) is used instead if allowed.
+fn debug_info_arguments(args: Vec<Arg>, z7: bool) -> Result<Vec<Arg>, CompilerError> {
+    let mut result = Vec::with_capacity(args.len());
+    for arg in args.into_iter() {
+        match arg {
+            Arg::Flag { ref flag, .. } if flag == "Zi" || flag == "ZI" => {
+                if !z7 {
+                    return Err(CompilerError::InvalidArguments(format!(
+                        "Debug information in shared PDB file is not supported: /{} (use /Z7 or msvc_z7 option)",
+                        flag
+                    )));
+                }
+                result.push(Arg::flag(Scope::Shared, "Z7"));
+            }
+            _ => result.push(arg),
+        }
+    }
+    Ok(result)
+}
+
 fn find_param<T, R, F: Fn(&T) -> Option<R>>(args: &[T], filter: F) -> ParamValue<R> {
     let mut found = Vec::from_iter(args.iter().filter_map(filter));
     match found.len() {
@@ -252,7 +358,9 @@ fn decode_utf16<F: Fn(u16, u16) -> u16>(data: &[u8], endian: F) -> Result<String
     String::from_utf16(&utf16).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
 }
 
-fn parse_arguments<S: AsRef<str>, I: Iterator<Item = S>>(mut iter: I) -> Result<Vec<Arg>, String> {
+fn parse_arguments<S: AsRef<str>, I: Iterator<Item = S>>(
+    mut iter: I,
+) -> Result<Vec<Arg>, CompilerError> {
     let mut result: Vec<Arg> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
     while let Some(parse_result) = parse_argument(&mut iter) {
@@ -266,10 +374,7 @@ fn parse_arguments<S: AsRef<str>, I: Iterator<Item = S>>(mut iter: I) -> Result<
         }
     }
     if !errors.is_empty() {
-        return Err(format!(
-            "Found unknown command line arguments: {:?}",
-            errors
-        ));
+        return Err(CompilerError::UnknownArguments(errors));
     }
     Ok(result)
 }
@@ -283,20 +388,21 @@ fn parse_argument<S: AsRef<str>, I: Iterator<Item = S>>(
             let flag = &arg.as_ref()[1..];
             match is_spaceable_param(flag) {
                 Some((prefix, scope)) => {
-                    if flag == prefix {
+                    let value = if flag == prefix {
                         match iter.next() {
-                            Some(value) => {
-                                if !has_param_prefix(value.as_ref()) {
-                                    Ok(Arg::param(scope, prefix, value.as_ref()))
-                                } else {
-                                    Err(arg.as_ref().to_string())
-                                }
+                            Some(ref value) if !has_param_prefix(value.as_ref()) => {
+                                value.as_ref().to_string()
                             }
-                            _ => Err(arg.as_ref().to_string()),
+                            _ => return Some(Err(arg.as_ref().to_string())),
                         }
                     } else {
-                        Ok(Arg::param(scope, prefix, &flag[prefix.len()..]))
-                    }
+                        flag[prefix.len()..].to_string()
+                    };
+                    Ok(match prefix {
+                        "ifcOutput" => Arg::output(OutputKind::Module, prefix, value),
+                        "reference" => Arg::input(InputKind::Module, prefix, value),
+                        _ => Arg::param(scope, prefix, value),
+                    })
                 }
                 None => match flag {
                     "c" | "nologo" => Ok(Arg::flag(Scope::Ignore, flag)),
@@ -307,7 +413,12 @@ fn parse_argument<S: AsRef<str>, I: Iterator<Item = S>>(
                     s if s.starts_with("RTC") => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with('Z') => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with("d2Zi+") => Ok(Arg::flag(Scope::Shared, flag)),
-                    s if s.starts_with("MP") => Ok(Arg::flag(Scope::Compiler, flag)),
+                    // Parallel compilation is handled by octobuild.
+                    s if s.starts_with("MP") => match s[2..].parse::<usize>() {
+                        Ok(_) => Ok(Arg::flag(Scope::Ignore, flag)),
+                        Err(_) if s == "MP" => Ok(Arg::flag(Scope::Ignore, flag)),
+                        Err(_) => Err(arg.as_ref().to_string()),
+                    },
                     s if s.starts_with("MD") => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with("MT") => Ok(Arg::flag(Scope::Shared, flag)),
                     s if s.starts_with("EH") => Ok(Arg::flag(Scope::Shared, flag)),
@@ -323,7 +434,23 @@ fn parse_argument<S: AsRef<str>, I: Iterator<Item = S>>(
                     s if s.starts_with("Yc") => Ok(Arg::output(OutputKind::Marker, "Yc", &s[2..])),
                     s if s.starts_with("Yu") => Ok(Arg::input(InputKind::Marker, "Yu", &s[2..])),
                     s if s.starts_with("Yl") => Ok(Arg::flag(Scope::Shared, flag)),
-                    s if s.starts_with("FI") => Ok(Arg::param(Scope::Preprocessor, "FI", &s[2..])),
+                    // Object file is compiled with embedded or temporary debug information.
+                    s if s.starts_with("Fd") => {
+                        Ok(Arg::output(OutputKind::DebugInfo, "Fd", &s[2..]))
+                    }
+                    // Executable file is not produced by compilation step.
+                    s if s.starts_with("Fe") => Ok(Arg::flag(Scope::Ignore, flag)),
+                    "FS" => Ok(Arg::flag(Scope::Compiler, flag)),
+                    // Module interface and partition units.
+                    "interface" | "internalPartition" => Ok(Arg::flag(Scope::Compiler, flag)),
+                    "permissive-" | "utf-8" | "sdl" | "sdl-" => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("std:") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("external:") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("experimental:") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("diagnostics:") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("d1") => Ok(Arg::flag(Scope::Shared, flag)),
+                    s if s.starts_with("guard:") => Ok(Arg::flag(Scope::Compiler, flag)),
+                    s if s.starts_with('Q') => Ok(Arg::flag(Scope::Compiler, flag)),
                     s if s.starts_with("analyze") => Ok(Arg::flag(Scope::Shared, flag)),
                     _ => Err(arg.as_ref().to_string()),
                 },
@@ -340,12 +467,18 @@ fn parse_argument<S: AsRef<str>, I: Iterator<Item = S>>(
 }
 
 fn is_spaceable_param(flag: &str) -> Option<(&str, Scope)> {
-    for prefix in ["D"].iter() {
+    // Module files are not passed as is.
+    for prefix in ["ifcOutput", "reference"].iter() {
+        if flag.starts_with(*prefix) {
+            return Some((*prefix, Scope::Ignore));
+        }
+    }
+    for prefix in ["D", "U"].iter() {
         if flag.starts_with(*prefix) {
             return Some((*prefix, Scope::Shared));
         }
     }
-    for prefix in ["I"].iter() {
+    for prefix in ["I", "AI", "FI", "FU", "external:I"].iter() {
         if flag.starts_with(*prefix) {
             return Some((*prefix, Scope::Preprocessor));
         }
@@ -386,6 +519,143 @@ fn test_parse_argument() {
     )
 }
 
+#[test]
+fn test_parse_argument_msvc() {
+    let args = Vec::from_iter(
+        "/c /permissive- /Zc:inline /Zc:__cplusplus /external:I ThirdParty /external:W0 \
+         /std:c++17 /utf-8 /FS /Fdsample.pdb /Fesample.exe /sdl /guard:cf \
+         /Qspectre /U NDEBUG /U_DEBUG /AI Assemblies /FU System.dll /FI Core.h \
+         /d1reportTime /experimental:module sample.cpp"
+            .split_whitespace()
+            .map(|x| x.to_string()),
+    );
+    assert_eq!(
+        parse_arguments(args.iter()).unwrap(),
+        [
+            Arg::flag(Scope::Ignore, "c"),
+            Arg::flag(Scope::Shared, "permissive-"),
+            Arg::flag(Scope::Shared, "Zc:inline"),
+            Arg::flag(Scope::Shared, "Zc:__cplusplus"),
+            Arg::param(Scope::Preprocessor, "external:I", "ThirdParty"),
+            Arg::flag(Scope::Shared, "external:W0"),
+            Arg::flag(Scope::Shared, "std:c++17"),
+            Arg::flag(Scope::Shared, "utf-8"),
+            Arg::flag(Scope::Compiler, "FS"),
+            Arg::output(OutputKind::DebugInfo, "Fd", "sample.pdb"),
+            Arg::flag(Scope::Ignore, "Fesample.exe"),
+            Arg::flag(Scope::Shared, "sdl"),
+            Arg::flag(Scope::Compiler, "guard:cf"),
+            Arg::flag(Scope::Compiler, "Qspectre"),
+            Arg::param(Scope::Shared, "U", "NDEBUG"),
+            Arg::param(Scope::Shared, "U", "_DEBUG"),
+            Arg::param(Scope::Preprocessor, "AI", "Assemblies"),
+            Arg::param(Scope::Preprocessor, "FU", "System.dll"),
+            Arg::param(Scope::Preprocessor, "FI", "Core.h"),
+            Arg::flag(Scope::Shared, "d1reportTime"),
+            Arg::flag(Scope::Shared, "experimental:module"),
+            Arg::input(InputKind::Source, "", "sample.cpp")
+        ]
+    );
+    // Listing file is produced by compiler and can't be cached.
+    assert!(parse_arguments(["/c", "/Fasample.asm", "sample.cpp"].iter()).is_err());
+    // Analysis results are cached as optional output.
+    assert!(parse_arguments(["/c", "/analyze", "sample.cpp"].iter()).is_ok());
+}
+
+#[test]
+fn test_debug_info_arguments() {
+    let args = || {
+        vec![
+            Arg::flag(Scope::Shared, "Zi"),
+            Arg::flag(Scope::Shared, "Zc:inline"),
+            Arg::output(OutputKind::DebugInfo, "Fd", "sample.pdb"),
+        ]
+    };
+    assert_eq!(
+        debug_info_arguments(args(), true).unwrap(),
+        [
+            Arg::flag(Scope::Shared, "Z7"),
+            Arg::flag(Scope::Shared, "Zc:inline"),
+            Arg::output(OutputKind::DebugInfo, "Fd", "sample.pdb"),
+        ]
+    );
+    assert!(debug_info_arguments(args(), false).is_err());
+    assert!(debug_info_arguments(vec![Arg::flag(Scope::Shared, "ZI")], false).is_err());
+    assert_eq!(
+        debug_info_arguments(vec![Arg::flag(Scope::Shared, "Z7")], false).unwrap(),
+        [Arg::flag(Scope::Shared, "Z7")]
+    );
+}
+
+#[cfg(test)]
+use crate::compiler::test::{split_args, test_command};
+
+#[test]
+fn test_create_tasks_parallel() {
+    let command = test_command("cl.exe", None);
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args("/c /MP4 /O2 a.cpp b.cpp c.cpp"),
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        tasks
+            .iter()
+            .map(|task| task.input_source.to_str().unwrap())
+            .collect::<Vec<&str>>(),
+        ["a.cpp", "b.cpp", "c.cpp"]
+    );
+    assert_eq!(tasks[0].shared.parallel_limit, Some(4));
+    // Ignored flag is not passed to compiler.
+    assert_eq!(tasks[0].shared.args[1], Arg::flag(Scope::Ignore, "MP4"));
+
+    let tasks = create_tasks(command.clone(), &split_args("/c /MP a.cpp b.cpp"), false).unwrap();
+    assert_eq!(tasks[0].shared.parallel_limit, None);
+    assert!(create_tasks(command, &split_args("/c /MPx a.cpp"), false).is_err());
+}
+
+#[test]
+fn test_create_tasks_modules() {
+    let command = test_command("cl.exe", Some("/build"));
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args("/c /std:c++latest /ifcOutput Engine.ifc /reference Core=Core.ifc Engine.ixx"),
+        false,
+    )
+    .unwrap();
+    assert_eq!(tasks[0].language, "P");
+    assert_eq!(
+        tasks[0].shared.output_module,
+        Some(PathBuf::from("/build/Engine.ifc"))
+    );
+    assert_eq!(
+        tasks[0].shared.input_modules,
+        [ModuleFile {
+            name: Some("Core".to_string()),
+            path: PathBuf::from("/build/Core.ifc"),
+        }]
+    );
+    // Preprocessed file is compiled as interface unit.
+    assert!(tasks[0]
+        .shared
+        .args
+        .contains(&Arg::flag(Scope::Compiler, "interface")));
+
+    let tasks = create_tasks(
+        command.clone(),
+        &split_args("/c /std:c++latest /reference Engine.ifc /ifcOutput Unused.ifc Game.cpp"),
+        false,
+    )
+    .unwrap();
+    assert_eq!(tasks[0].shared.output_module, None);
+    assert_eq!(tasks[0].shared.input_modules[0].name, None);
+
+    assert!(create_tasks(command, &split_args("/c /interface Engine.cpp"), false).is_err());
+}
+
 #[test]
 fn test_decode_string() {
     // ANSI
@@ -1,18 +1,41 @@
 use std::borrow::Cow;
-use std::cmp::{max, min};
+use std::cmp::{max, min, Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
 use std::io::{Error, ErrorKind};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
+use log::warn;
 use petgraph::graph::NodeIndex;
 use petgraph::{EdgeDirection, Graph};
 
-use crate::compiler::{CommandInfo, CompilationTask, Compiler, OutputInfo, SharedState, Toolchain};
+use crate::compiler::{
+    CommandInfo, CompilationTask, Compiler, OutputInfo, OutputOrigin, SharedState, Toolchain,
+};
+use crate::io::history::TaskHistory;
 
 pub type BuildGraph = Graph<Arc<BuildTask>, ()>;
 
 pub struct BuildTask {
     pub title: String,
     pub action: BuildAction,
+    pub options: TaskOptions,
+}
+
+// Task execution options (XGE task and tool attributes).
+#[derive(Clone, Debug)]
+pub struct TaskOptions {
+    // Task can be executed on remote builder
+    pub allow_remote: bool,
+    // Task can be restarted on local host after remote failure
+    pub allow_restart_on_local: bool,
+    // Task failure stops whole build
+    pub stop_on_errors: bool,
+    // Task is skipped if any task of the same project failed
+    pub skip_if_project_failed: bool,
+    // Project index for failure tracking
+    pub project: Option<usize>,
 }
 
 pub enum BuildAction {
@@ -28,22 +51,128 @@ pub struct BuildResult<'a> {
     pub worker: usize,
     // Build result
     pub result: &'a Result<OutputInfo, Error>,
+    // Task start time
+    pub start: Instant,
+    // Task execution duration
+    pub duration: Duration,
     // Completed task count
     pub completed: usize,
     // Total task count
     pub total: usize,
 }
 
+// Estimated duration of command execution without history.
+const DEFAULT_EXEC_COST: Duration = Duration::from_secs(1);
+// Estimated compilation duration per source kilobyte without history.
+const SOURCE_KB_COST: Duration = Duration::from_millis(1);
+
 struct ResultMessage {
     index: NodeIndex,
     task: Arc<BuildTask>,
     worker: usize,
     result: Result<OutputInfo, Error>,
+    start: Instant,
+    duration: Duration,
 }
 
 struct TaskMessage {
     index: NodeIndex,
     task: Arc<BuildTask>,
+    // Longest remaining path from this task to the end of build
+    priority: Duration,
+}
+
+// Ready tasks ordered by critical path length.
+struct TaskQueue {
+    priorities: Vec<Duration>,
+    state: Mutex<TaskQueueState>,
+    cond: Condvar,
+}
+
+struct TaskQueueState {
+    heap: BinaryHeap<TaskMessage>,
+    closed: bool,
+}
+
+impl Default for TaskOptions {
+    fn default() -> Self {
+        TaskOptions {
+            allow_remote: true,
+            allow_restart_on_local: true,
+            stop_on_errors: true,
+            skip_if_project_failed: false,
+            project: None,
+        }
+    }
+}
+
+impl PartialEq for TaskMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TaskMessage {}
+
+impl PartialOrd for TaskMessage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TaskMessage {
+    // Longest path first, graph order for equal paths.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, Reverse(self.index)).cmp(&(other.priority, Reverse(other.index)))
+    }
+}
+
+impl TaskQueue {
+    fn new(priorities: Vec<Duration>) -> Self {
+        TaskQueue {
+            priorities,
+            state: Mutex::new(TaskQueueState {
+                heap: BinaryHeap::new(),
+                closed: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Add tasks at once: workers must see all ready tasks to choose by priority.
+    fn extend<I: IntoIterator<Item = NodeIndex>>(&self, graph: &BuildGraph, ready: I) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .heap
+            .extend(ready.into_iter().map(|index| TaskMessage {
+                index,
+                task: graph.node_weight(index).unwrap().clone(),
+                priority: self.priorities[index.index()],
+            }));
+        self.cond.notify_all();
+    }
+
+    // Wait for task with highest priority. Returns None after queue is closed.
+    fn pop(&self) -> Option<TaskMessage> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(message) = state.heap.pop() {
+                return Some(message);
+            }
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    // Drop queued tasks and stop workers.
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.heap.clear();
+        self.cond.notify_all();
+    }
 }
 
 impl<'a> BuildResult<'a> {
@@ -53,6 +182,8 @@ impl<'a> BuildResult<'a> {
             worker: message.worker,
             task: &message.task,
             result: &message.result,
+            start: message.start,
+            duration: message.duration,
             completed: *completed,
             total,
         }
@@ -66,22 +197,33 @@ impl BuildAction {
         args: &[String],
         title: &str,
     ) -> Vec<BuildAction> {
+        match BuildAction::try_create_tasks(compiler, command.clone(), args) {
+            Ok(actions) => actions,
+            Err(e) => {
+                warn!("Can't use octobuild for task {}: {}", title, e);
+                vec![BuildAction::Exec(command, args.to_vec())]
+            }
+        }
+    }
+
+    // Create compilation actions without fallback to command execution.
+    pub fn try_create_tasks<C: Compiler>(
+        compiler: &C,
+        command: CommandInfo,
+        args: &[String],
+    ) -> Result<Vec<BuildAction>, Error> {
         let actions: Vec<BuildAction> = compiler
-            .create_tasks(command.clone(), &args)
-            .map(|tasks| {
-                tasks
-                    .into_iter()
-                    .map(|(toolchain, task)| BuildAction::Compilation(toolchain, task))
-                    .collect()
-            })
-            .unwrap_or_else(|e| {
-                println!("Can't use octobuild for task {}: {}", title, e);
-                Vec::new()
-            });
+            .create_tasks(command, args)?
+            .into_iter()
+            .map(|(toolchain, task)| BuildAction::Compilation(toolchain, task))
+            .collect();
         if actions.is_empty() {
-            return vec![BuildAction::Exec(command, args.to_vec())];
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "compilation tasks not found",
+            ));
         }
-        actions
+        Ok(actions)
     }
 
     pub fn title(self: &Self) -> Cow<str> {
@@ -129,9 +271,11 @@ pub fn validate_graph<N, E>(graph: Graph<N, E>) -> Result<Graph<N, E>, Error> {
 
 fn execute_until_failed<F>(
     graph: &BuildGraph,
-    tx_task: crossbeam::Sender<TaskMessage>,
+    queue: &TaskQueue,
     rx_result: &crossbeam::Receiver<ResultMessage>,
     count: &mut usize,
+    keep_going: bool,
+    history: &TaskHistory,
     update_progress: F,
 ) -> Result<Option<i32>, Error>
 where
@@ -141,39 +285,83 @@ where
     for _ in 0..graph.node_count() {
         completed.push(false);
     }
-    for index in graph.externals(EdgeDirection::Outgoing) {
-        tx_task
-            .send(TaskMessage {
-                index,
-                task: graph.node_weight(index).unwrap().clone(),
-            })
-            .map_err(|e| Error::new(ErrorKind::Other, e))?;
-    }
+    queue.extend(graph, graph.externals(EdgeDirection::Outgoing));
 
+    // Status of first failed task which doesn't stop build.
+    let mut failed: Option<Option<i32>> = None;
+    // First task execution error in keep going mode.
+    let mut error: Option<Error> = None;
+    let mut failed_projects: HashSet<usize> = HashSet::new();
     for message in rx_result.iter() {
         assert!(!completed[message.index.index()]);
 
         update_progress(BuildResult::new(&message, count, graph.node_count()))?;
-        let result = message.result?;
-        if !result.success() {
-            let status = result.status;
-            return Ok(status);
+        // Only local and remote executions tell real task duration.
+        if let Ok(ref output) = message.result {
+            if output.origin != OutputOrigin::Cache
+                && !matches!(message.task.action, BuildAction::Empty)
+            {
+                history.update(&task_key(&message.task), message.duration);
+            }
+        }
+        let success = match message.result {
+            Ok(ref output) if !output.success() => {
+                if message.task.options.stop_on_errors && !keep_going {
+                    return Ok(output.status);
+                }
+                failed.get_or_insert(output.status);
+                false
+            }
+            Ok(_) => true,
+            // Task which can't be executed is failed task in keep going mode.
+            Err(e) if keep_going => {
+                error.get_or_insert(e);
+                false
+            }
+            Err(e) => return Err(e),
+        };
+        if !success {
+            if let Some(project) = message.task.options.project {
+                failed_projects.insert(project);
+            }
         }
         completed[message.index.index()] = true;
 
-        for source in graph.neighbors_directed(message.index, EdgeDirection::Incoming) {
-            if is_ready(graph, &completed, source) {
-                tx_task
-                    .send(TaskMessage {
-                        index: source,
-                        task: graph.node_weight(source).unwrap().clone(),
-                    })
-                    .map_err(|e| Error::new(ErrorKind::Other, e))?;
+        // Schedule ready tasks. Dependents of failed or skipped tasks are skipped.
+        let mut ready = Vec::new();
+        let mut finished = vec![(message.index, success)];
+        while let Some((index, success)) = finished.pop() {
+            for source in graph.neighbors_directed(index, EdgeDirection::Incoming) {
+                if completed[source.index()] {
+                    continue;
+                }
+                if success && !is_ready(graph, &completed, source) {
+                    continue;
+                }
+                let task = graph.node_weight(source).unwrap();
+                let skip = !success
+                    || (task.options.skip_if_project_failed
+                        && task
+                            .options
+                            .project
+                            .iter()
+                            .any(|p| failed_projects.contains(p)));
+                if skip {
+                    completed[source.index()] = true;
+                    *count += 1;
+                    finished.push((source, false));
+                } else {
+                    ready.push(source);
+                }
             }
         }
+        queue.extend(graph, ready);
 
         if *count == completed.len() {
-            return Ok(Some(0));
+            return match error {
+                Some(e) => Err(e),
+                None => Ok(failed.unwrap_or(Some(0))),
+            };
         }
     }
     panic!("Unexpected end of result pipe");
@@ -192,6 +380,7 @@ pub fn execute_graph<F>(
     state: &SharedState,
     build_graph: BuildGraph,
     process_limit: usize,
+    keep_going: bool,
     update_progress: F,
 ) -> Result<Option<i32>, Error>
 where
@@ -204,30 +393,37 @@ where
 
     if graph.node_count() == 1 {
         let task = &graph.raw_nodes()[0].weight;
+        let start = Instant::now();
         let result = execute_compiler(&state, task);
         update_progress(BuildResult {
             worker: 0,
             completed: 1,
             total: 1,
             result: &result,
+            start,
+            duration: start.elapsed(),
             task,
         })?;
         return result.map(|output| output.status);
     }
 
     let (tx_result, rx_result) = crossbeam::unbounded::<ResultMessage>();
-    let (tx_task, rx_task) = crossbeam::unbounded::<TaskMessage>();
+    let queue = TaskQueue::new(critical_path(&graph, &state.history)?);
     let num_cpus = max(1, min(process_limit, graph.node_count()));
-    crossbeam::scope(|scope| {
+    let result = crossbeam::scope(|scope| {
         for worker_id in 0..num_cpus {
-            let local_rx_task = rx_task.clone();
+            let local_queue = &queue;
             let local_tx_result = tx_result.clone();
             scope.spawn(move |_| {
-                while let Ok(message) = local_rx_task.recv() {
+                while let Some(message) = local_queue.pop() {
+                    let start = Instant::now();
+                    let result = execute_compiler(state, &message.task);
                     match local_tx_result.send(ResultMessage {
                         index: message.index,
                         worker: worker_id,
-                        result: execute_compiler(state, &message.task),
+                        result,
+                        start,
+                        duration: start.elapsed(),
                         task: message.task,
                     }) {
                         Ok(_) => {}
@@ -241,17 +437,67 @@ where
         drop(tx_result);
         // Run all tasks.
         let mut count: usize = 0;
-        let result =
-            execute_until_failed(&graph, tx_task, &rx_result, &mut count, &update_progress);
+        let result = execute_until_failed(
+            &graph,
+            &queue,
+            &rx_result,
+            &mut count,
+            keep_going,
+            &state.history,
+            &update_progress,
+        );
         // Cleanup task queue.
-        for _ in rx_task.try_iter() {}
+        queue.close();
         // Wait for in progress task completion.
         for message in rx_result.iter() {
             update_progress(BuildResult::new(&message, &mut count, graph.node_count()))?;
         }
         result
     })
-    .unwrap()
+    .unwrap();
+    let _ = state.history.save();
+    result
+}
+
+fn task_key(task: &BuildTask) -> String {
+    format!("{}\n{}", task.title, task.action.title())
+}
+
+// Estimated task duration: from previous builds or from source file size.
+fn task_cost(task: &BuildTask, history: &TaskHistory) -> Duration {
+    if let BuildAction::Empty = task.action {
+        return Duration::from_secs(0);
+    }
+    if let Some(duration) = history.duration(&task_key(task)) {
+        return duration;
+    }
+    match task.action {
+        BuildAction::Compilation(_, ref task) => fs::metadata(&task.input_source)
+            .map(|meta| SOURCE_KB_COST * ((meta.len() / 1024) as u32 + 1))
+            .unwrap_or(DEFAULT_EXEC_COST),
+        _ => DEFAULT_EXEC_COST,
+    }
+}
+
+// Longest path from every task to the end of build (including task itself).
+fn critical_path(graph: &BuildGraph, history: &TaskHistory) -> Result<Vec<Duration>, Error> {
+    let order = petgraph::algo::toposort(graph, None).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "Found cycles in build dependencies",
+        )
+    })?;
+    let mut priorities = vec![Duration::from_secs(0); graph.node_count()];
+    // Dependents are placed before their dependencies in topological order.
+    for index in order {
+        let tail = graph
+            .neighbors_directed(index, EdgeDirection::Incoming)
+            .map(|dependent| priorities[dependent.index()])
+            .max()
+            .unwrap_or_else(|| Duration::from_secs(0));
+        priorities[index.index()] = tail + task_cost(&graph[index], history);
+    }
+    Ok(priorities)
 }
 
 fn execute_compiler(state: &SharedState, task: &BuildTask) -> Result<OutputInfo, Error> {
@@ -260,6 +506,8 @@ fn execute_compiler(state: &SharedState, task: &BuildTask) -> Result<OutputInfo,
             status: Some(0),
             stderr: Vec::new(),
             stdout: Vec::new(),
+            origin: OutputOrigin::Local,
+            agent: None,
         }),
         BuildAction::Exec(ref command, ref args) => state.wrap_slow(|| {
             command
@@ -278,16 +526,36 @@ fn execute_compiler(state: &SharedState, task: &BuildTask) -> Result<OutputInfo,
 mod test {
     use std::sync::{Arc, Mutex};
 
+    use std::path::Path;
+
+    use tempdir::TempDir;
+
     use crate::compiler::SharedState;
     use crate::config::Config;
 
     use super::*;
 
+    // Shared state with private cache directory.
+    fn create_state(temp_dir: &TempDir) -> SharedState {
+        let mut config = Config::defaults().unwrap();
+        config.cache_dir = temp_dir.path().to_path_buf();
+        SharedState::new(&config).unwrap()
+    }
+
+    fn exec_task(title: &str, program: &str) -> BuildTask {
+        BuildTask {
+            title: title.to_string(),
+            action: BuildAction::Exec(CommandInfo::simple(Path::new(program)), Vec::new()),
+            options: TaskOptions::default(),
+        }
+    }
+
     #[test]
     fn test_execute_graph_empty() {
-        let state = SharedState::new(&Config::defaults().unwrap()).unwrap();
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
         let graph = BuildGraph::new();
-        execute_graph(&state, graph, 2, |_| {
+        execute_graph(&state, graph, 2, false, |_| {
             unreachable!();
         })
         .unwrap();
@@ -295,17 +563,19 @@ mod test {
 
     #[test]
     fn test_execute_graph_single() {
-        let state = SharedState::new(&Config::defaults().unwrap()).unwrap();
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
 
         // Simple two tasks graph
         let mut graph = BuildGraph::new();
         graph.add_node(Arc::new(BuildTask {
             title: "task 1".to_string(),
             action: BuildAction::Empty,
+            options: Default::default(),
         }));
 
         let result = Mutex::new(Vec::new());
-        execute_graph(&state, graph, 4, |r| {
+        execute_graph(&state, graph, 4, false, |r| {
             result.lock().unwrap().push(r.task.title.clone());
             Ok(())
         })
@@ -318,28 +588,196 @@ mod test {
     // Test for #19 issue (https://github.com/bozaro/octobuild/issues/19)
     #[test]
     fn test_execute_graph_no_hang() {
-        let state = SharedState::new(&Config::defaults().unwrap()).unwrap();
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
 
         // Simple two tasks graph
         let mut graph = BuildGraph::new();
         let t1 = graph.add_node(Arc::new(BuildTask {
             title: "task 1".to_string(),
             action: BuildAction::Empty,
+            options: Default::default(),
+        }));
+        let t2 = graph.add_node(Arc::new(BuildTask {
+            title: "task 2".to_string(),
+            action: BuildAction::Empty,
+            options: Default::default(),
+        }));
+        graph.add_edge(t2, t1, ());
+
+        let result = Mutex::new(Vec::new());
+        execute_graph(&state, graph, 4, false, |r| {
+            result.lock().unwrap().push(r.task.title.clone());
+            Ok(())
+        })
+        .unwrap();
+
+        let actual: Vec<String> = result.lock().unwrap().clone();
+        assert_eq!(actual, vec!["task 1".to_string(), "task 2".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_graph_continue_on_errors() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
+        let options = TaskOptions {
+            stop_on_errors: false,
+            skip_if_project_failed: true,
+            project: Some(0),
+            ..Default::default()
+        };
+
+        // Failed task doesn't stop build, but skips dependent and same project tasks
+        let mut graph = BuildGraph::new();
+        let t1 = graph.add_node(Arc::new(BuildTask {
+            title: "task 1".to_string(),
+            action: BuildAction::Exec(CommandInfo::simple(Path::new("false")), Vec::new()),
+            options: options.clone(),
         }));
         let t2 = graph.add_node(Arc::new(BuildTask {
             title: "task 2".to_string(),
             action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        let t3 = graph.add_node(Arc::new(BuildTask {
+            title: "task 3".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        let t4 = graph.add_node(Arc::new(BuildTask {
+            title: "task 4".to_string(),
+            action: BuildAction::Empty,
+            options: options.clone(),
         }));
         graph.add_edge(t2, t1, ());
+        graph.add_edge(t4, t3, ());
 
         let result = Mutex::new(Vec::new());
-        execute_graph(&state, graph, 4, |r| {
+        let status = execute_graph(&state, graph, 1, false, |r| {
             result.lock().unwrap().push(r.task.title.clone());
             Ok(())
         })
         .unwrap();
 
+        assert_eq!(status, Some(1));
+        let actual: Vec<String> = result.lock().unwrap().clone();
+        assert_eq!(actual, vec!["task 1".to_string(), "task 3".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_graph_keep_going() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
+
+        // Keep going overrides StopOnErrors, but dependent task is skipped
+        let mut graph = BuildGraph::new();
+        let t1 = graph.add_node(Arc::new(exec_task("task 1", "false")));
+        graph.add_node(Arc::new(BuildTask {
+            title: "task 2".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        let t3 = graph.add_node(Arc::new(BuildTask {
+            title: "task 3".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        graph.add_edge(t3, t1, ());
+
+        let result = Mutex::new(Vec::new());
+        let status = execute_graph(&state, graph, 1, true, |r| {
+            result.lock().unwrap().push(r.task.title.clone());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(status, Some(1));
+        let actual: Vec<String> = result.lock().unwrap().clone();
+        assert_eq!(actual, vec!["task 1".to_string(), "task 2".to_string()]);
+    }
+
+    #[test]
+    fn test_execute_graph_keep_going_error() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
+
+        // Task which can't be started doesn't stop build in keep going mode
+        let mut graph = BuildGraph::new();
+        let missing = temp_dir.path().join("missing-program");
+        let t1 = graph.add_node(Arc::new(exec_task("task 1", missing.to_str().unwrap())));
+        graph.add_node(Arc::new(BuildTask {
+            title: "task 2".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        let t3 = graph.add_node(Arc::new(BuildTask {
+            title: "task 3".to_string(),
+            action: BuildAction::Empty,
+            options: TaskOptions::default(),
+        }));
+        graph.add_edge(t3, t1, ());
+
+        let result = Mutex::new(Vec::new());
+        let status = execute_graph(&state, graph, 1, true, |r| {
+            result.lock().unwrap().push(r.task.title.clone());
+            Ok(())
+        });
+
+        assert!(status.is_err());
         let actual: Vec<String> = result.lock().unwrap().clone();
         assert_eq!(actual, vec!["task 1".to_string(), "task 2".to_string()]);
     }
+
+    #[test]
+    fn test_critical_path() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let history = TaskHistory::new(temp_dir.path().join("history"), false);
+
+        let mut graph = BuildGraph::new();
+        let t1 = graph.add_node(Arc::new(exec_task("task 1", "true")));
+        let t2 = graph.add_node(Arc::new(exec_task("task 2", "true")));
+        let t3 = graph.add_node(Arc::new(exec_task("task 3", "true")));
+        graph.add_edge(t2, t1, ());
+        history.update(&task_key(&graph[t1]), Duration::from_secs(3));
+        history.update(&task_key(&graph[t2]), Duration::from_secs(1));
+        history.update(&task_key(&graph[t3]), Duration::from_secs(2));
+
+        assert_eq!(
+            critical_path(&graph, &history).unwrap(),
+            vec![
+                Duration::from_secs(4),
+                Duration::from_secs(1),
+                Duration::from_secs(2)
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_graph_critical_path_first() {
+        let temp_dir = TempDir::new("octobuild").unwrap();
+        let state = create_state(&temp_dir);
+
+        let mut graph = BuildGraph::new();
+        let t1 = graph.add_node(Arc::new(exec_task("task 1", "true")));
+        let t2 = graph.add_node(Arc::new(exec_task("task 2", "true")));
+        state
+            .history
+            .update(&task_key(&graph[t1]), Duration::from_secs(1));
+        state
+            .history
+            .update(&task_key(&graph[t2]), Duration::from_secs(3));
+
+        let result = Mutex::new(Vec::new());
+        execute_graph(&state, graph, 1, false, |r| {
+            result.lock().unwrap().push(r.task.title.clone());
+            Ok(())
+        })
+        .unwrap();
+
+        let actual: Vec<String> = result.lock().unwrap().clone();
+        assert_eq!(actual, vec!["task 2".to_string(), "task 1".to_string()]);
+    }
 }
@@ -8,6 +8,7 @@ use std::sync::Arc;
 
 use crate::cmd;
 use crate::compiler::{CommandEnv, CommandInfo};
+use crate::worker::TaskOptions;
 
 use petgraph::graph::{Graph, NodeIndex};
 use xml::reader::EventReader;
@@ -18,6 +19,7 @@ pub struct XgNode {
     pub title: String,
     pub command: CommandInfo,
     pub args: Vec<String>,
+    pub options: TaskOptions,
 }
 
 pub type XgGraph = Graph<XgNode, ()>;
@@ -25,6 +27,7 @@ pub type XgGraph = Graph<XgNode, ()>;
 #[derive(Debug)]
 pub enum XgParseError {
     AttributeNotFound(&'static str),
+    InvalidAttribute(&'static str, String),
     EnvironmentNotFound(String),
     ToolNotFound(String),
     DependencyNotFound(String),
@@ -37,6 +40,9 @@ impl Display for XgParseError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), ::std::fmt::Error> {
         match self {
             XgParseError::AttributeNotFound(ref attr) => write!(f, "attribute not found: {}", attr),
+            XgParseError::InvalidAttribute(ref attr, ref value) => {
+                write!(f, "invalid attribute {} value: {}", attr, value)
+            }
             XgParseError::EnvironmentNotFound(ref id) => {
                 write!(f, "сan't find environment with id: {}", id)
             }
@@ -55,6 +61,7 @@ impl ::std::error::Error for XgParseError {
     fn description(&self) -> &str {
         match self {
             XgParseError::AttributeNotFound(_) => "attribute not found",
+            XgParseError::InvalidAttribute(_, _) => "invalid attribute value",
             XgParseError::EnvironmentNotFound(_) => "сan't find environment by id",
             XgParseError::ToolNotFound(_) => "сan't find tool by id",
             XgParseError::DependencyNotFound(_) => "сan't find task for dependency by id",
@@ -77,7 +84,9 @@ struct XgEnvironment {
 
 #[derive(Debug)]
 struct XgProject {
+    name: String,
     env: String,
+    depends_on: Vec<String>,
     tasks: HashMap<String, XgTask>,
 }
 
@@ -87,6 +96,7 @@ struct XgTask {
     tool: String,
     working_dir: PathBuf,
     depends_on: Vec<String>,
+    options: XgOptions,
 }
 
 #[derive(Debug)]
@@ -94,6 +104,16 @@ struct XgTool {
     exec: PathBuf,
     args: String,
     output: Option<String>,
+    options: XgOptions,
+}
+
+// Execution attributes: task values override tool values.
+#[derive(Debug, Default)]
+struct XgOptions {
+    allow_remote: Option<bool>,
+    allow_restart_on_local: Option<bool>,
+    stop_on_errors: Option<bool>,
+    skip_if_project_failed: Option<bool>,
 }
 
 pub fn parse<R: Read>(graph: &mut XgGraph, reader: R) -> Result<(), Error> {
@@ -128,7 +148,9 @@ pub fn parse_build_set<R: Read>(
                 "Project" => {
                     let mut attrs = map_attributes(attributes);
                     projects.push(XgProject {
+                        name: attrs.remove("Name").unwrap_or_default(),
                         env: take_attr(&mut attrs, "Env")?,
+                        depends_on: split_depends(attrs.remove("DependsOn")),
                         tasks: parse_tasks(events)?,
                     });
                 }
@@ -157,7 +179,12 @@ fn parse_environments<R: Read>(
                 "Environment" => {
                     let mut attrs = map_attributes(attributes);
                     let name = take_attr(&mut attrs, "Name")?;
-                    envs.insert(name, parse_environment(events)?);
+                    let env = parse_environment(events)?;
+                    // Environment with same name extends previous declaration.
+                    match envs.remove(&name) {
+                        Some(base) => envs.insert(name, merge_environment(base, env)),
+                        None => envs.insert(name, env),
+                    };
                 }
                 _ => {
                     parse_skip(events, ())?;
@@ -195,6 +222,19 @@ fn parse_environment<R: Read>(events: &mut EventReader<R>) -> Result<XgEnvironme
     })
 }
 
+fn merge_environment(base: XgEnvironment, env: XgEnvironment) -> XgEnvironment {
+    let mut variables = CommandEnv::new();
+    for (key, value) in base.variables.iter().chain(env.variables.iter()) {
+        variables.insert(key.clone(), value.clone());
+    }
+    let mut tools = base.tools;
+    tools.extend(env.tools);
+    XgEnvironment {
+        variables: Arc::new(variables),
+        tools,
+    }
+}
+
 fn parse_variables<R: Read>(
     events: &mut EventReader<R>,
     variables: &mut CommandEnv,
@@ -239,6 +279,7 @@ fn parse_tools<R: Read>(
                             exec: Path::new(&exec).to_path_buf(),
                             output: attrs.remove("OutputPrefix"),
                             args: attrs.remove("Params").unwrap_or_else(String::new),
+                            options: parse_options(&mut attrs)?,
                         },
                     );
                 }
@@ -264,19 +305,15 @@ fn parse_tasks<R: Read>(events: &mut EventReader<R>) -> Result<HashMap<String, X
                     let name = take_attr(&mut attrs, "Name")?;
                     let tool = take_attr(&mut attrs, "Tool")?;
                     let working_dir = take_attr(&mut attrs, "WorkingDir")?;
-                    // DependsOn
-                    let depends_on: HashSet<String> = match attrs.remove("DependsOn") {
-                        Some(v) => HashSet::from_iter(v.split(';').map(|v| v.to_string())),
-                        _ => HashSet::new(),
-                    };
-
+                    let depends_on = split_depends(attrs.remove("DependsOn"));
                     tasks.insert(
                         name.clone(),
                         XgTask {
                             title: attrs.remove("Caption"),
                             tool,
                             working_dir: Path::new(&working_dir).to_path_buf(),
-                            depends_on: depends_on.into_iter().collect::<Vec<String>>(),
+                            depends_on,
+                            options: parse_options(&mut attrs)?,
                         },
                     );
                 }
@@ -290,6 +327,38 @@ fn parse_tasks<R: Read>(events: &mut EventReader<R>) -> Result<HashMap<String, X
     }
 }
 
+fn parse_options(attrs: &mut HashMap<String, String>) -> Result<XgOptions, Error> {
+    Ok(XgOptions {
+        allow_remote: take_bool_attr(attrs, "AllowRemote")?,
+        allow_restart_on_local: take_bool_attr(attrs, "AllowRestartOnLocal")?,
+        stop_on_errors: take_bool_attr(attrs, "StopOnErrors")?,
+        skip_if_project_failed: take_bool_attr(attrs, "SkipIfProjectFailed")?,
+    })
+}
+
+fn task_options(task: &XgOptions, tool: &XgOptions, project: usize) -> TaskOptions {
+    let defaults = TaskOptions::default();
+    TaskOptions {
+        allow_remote: task
+            .allow_remote
+            .or(tool.allow_remote)
+            .unwrap_or(defaults.allow_remote),
+        allow_restart_on_local: task
+            .allow_restart_on_local
+            .or(tool.allow_restart_on_local)
+            .unwrap_or(defaults.allow_restart_on_local),
+        stop_on_errors: task
+            .stop_on_errors
+            .or(tool.stop_on_errors)
+            .unwrap_or(defaults.stop_on_errors),
+        skip_if_project_failed: task
+            .skip_if_project_failed
+            .or(tool.skip_if_project_failed)
+            .unwrap_or(defaults.skip_if_project_failed),
+        project: Some(project),
+    }
+}
+
 fn parse_skip<R: Read, T>(events: &mut EventReader<R>, result: T) -> Result<T, Error> {
     let mut depth: isize = 0;
     loop {
@@ -315,30 +384,58 @@ fn next_xml_event<R: Read>(reader: &mut EventReader<R>) -> Result<XmlEvent, Erro
         .map_err(|e| Error::new(ErrorKind::InvalidInput, XgParseError::XmlError(e)))
 }
 
+// Unique dependency list from DependsOn attribute.
+fn split_depends(value: Option<String>) -> Vec<String> {
+    match value {
+        Some(v) => HashSet::<String>::from_iter(
+            v.split(';')
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string()),
+        )
+        .into_iter()
+        .collect(),
+        None => Vec::new(),
+    }
+}
+
 fn parse_create_graph(
     graph: &mut XgGraph,
     envs: HashMap<String, XgEnvironment>,
     projects: Vec<XgProject>,
 ) -> Result<(), Error> {
-    for project in projects.into_iter() {
+    // Graph-wide project identifiers: graph can contain projects from other files.
+    let project_base = graph
+        .raw_nodes()
+        .iter()
+        .filter_map(|node| node.weight.options.project)
+        .max()
+        .map_or(0, |project| project + 1);
+    // Task nodes by project index and task id.
+    let mut task_refs: HashMap<(usize, &str), NodeIndex> = HashMap::new();
+    for (index, project) in projects.iter().enumerate() {
         let env = envs.get(&project.env).ok_or_else(|| {
             Error::new(
                 ErrorKind::InvalidInput,
                 XgParseError::EnvironmentNotFound(project.env.clone()),
             )
         })?;
-        graph_project(graph, project, env)?;
+        graph_project(graph, &mut task_refs, project, index, project_base, env)?;
+    }
+    for index in 0..projects.len() {
+        graph_depends(graph, &task_refs, &projects, index)?;
     }
     Ok(())
 }
 
-fn graph_project(
+fn graph_project<'a>(
     graph: &mut XgGraph,
-    project: XgProject,
+    task_refs: &mut HashMap<(usize, &'a str), NodeIndex>,
+    project: &'a XgProject,
+    project_index: usize,
+    project_base: usize,
     env: &XgEnvironment,
 ) -> Result<(), Error> {
-    let mut nodes: Vec<NodeIndex> = Vec::new();
-    let mut task_refs: HashMap<&str, NodeIndex> = HashMap::new();
     for (id, task) in project.tasks.iter() {
         let tool = env.tools.get(&task.tool).ok_or_else(|| {
             Error::new(
@@ -359,20 +456,70 @@ fn graph_project(
                 env: env.variables.clone(),
             },
             args: cmd::native::parse(&tool.args)?,
+            options: task_options(&task.options, &tool.options, project_base + project_index),
         });
-        task_refs.insert(&id, node);
-        nodes.push(node);
+        task_refs.insert((project_index, id), node);
     }
+    Ok(())
+}
+
+// Task dependency is "Task" in same project or "Project::Task" in other project.
+// Project dependency makes project start tasks depend on all final tasks of other project.
+fn graph_depends(
+    graph: &mut XgGraph,
+    task_refs: &HashMap<(usize, &str), NodeIndex>,
+    projects: &[XgProject],
+    project_index: usize,
+) -> Result<(), Error> {
+    let project = &projects[project_index];
+    let not_found = |name: String| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            XgParseError::DependencyNotFound(name),
+        )
+    };
     for (src_id, task) in project.tasks.iter() {
-        let src = task_refs.get(&src_id[..]).unwrap();
+        let src = task_refs[&(project_index, &src_id[..])];
         for dst_id in task.depends_on.iter() {
-            let dst = task_refs.get(&dst_id[..]).ok_or_else(|| {
-                Error::new(
-                    ErrorKind::InvalidInput,
-                    XgParseError::DependencyNotFound(dst_id.clone()),
-                )
-            })?;
-            graph.add_edge(*src, *dst, ());
+            let (dst_project, dst_task, dst_index) = match dst_id.find("::") {
+                Some(pos) => {
+                    let name = &dst_id[..pos];
+                    let index = projects.iter().position(|p| p.name == name);
+                    (name, &dst_id[pos + 2..], index)
+                }
+                None => (&project.name[..], &dst_id[..], Some(project_index)),
+            };
+            let dst = dst_index
+                .and_then(|index| task_refs.get(&(index, dst_task)))
+                .ok_or_else(|| not_found(format!("{}::{}", dst_project, dst_task)))?;
+            graph.add_edge(src, *dst, ());
+        }
+    }
+    for dst_name in project.depends_on.iter() {
+        let dst_index = projects
+            .iter()
+            .position(|p| &p.name == dst_name)
+            .ok_or_else(|| not_found(dst_name.clone()))?;
+        let dst_project = &projects[dst_index];
+        let finals: Vec<NodeIndex> = dst_project
+            .tasks
+            .keys()
+            .filter(|id| {
+                !dst_project
+                    .tasks
+                    .values()
+                    .any(|t| t.depends_on.contains(id))
+            })
+            .map(|id| task_refs[&(dst_index, &id[..])])
+            .collect();
+        for (src_id, task) in project.tasks.iter() {
+            if task.depends_on.iter().any(|id| !id.contains("::")) {
+                continue;
+            }
+            let src = task_refs[&(project_index, &src_id[..])];
+            for dst in finals.iter() {
+                graph.add_edge(src, *dst, ());
+            }
         }
     }
     Ok(())
@@ -391,6 +538,23 @@ fn take_attr(attrs: &mut HashMap<String, String>, attr: &'static str) -> Result<
     })
 }
 
+fn take_bool_attr(
+    attrs: &mut HashMap<String, String>,
+    attr: &'static str,
+) -> Result<Option<bool>, Error> {
+    match attrs.remove(attr) {
+        Some(value) => match value.to_lowercase().as_str() {
+            "true" => Ok(Some(true)),
+            "false" => Ok(Some(false)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                XgParseError::InvalidAttribute(attr, value),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
 #[test]
 fn test_parse_smoke() {
     use std::fs::File;
@@ -402,3 +566,139 @@ fn test_parse_smoke() {
     )
     .unwrap();
 }
+
+#[cfg(test)]
+const TASK_OPTIONS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<BuildSet FormatVersion="1">
+  <Environments>
+    <Environment Name="Default">
+      <Tools>
+        <Tool Name="Compile" AllowRemote="true" GroupPrefix="Compiling..." OutputFileMasks="*.obj, *.pdb" AutoReserveMemory="*.gch" Path="cl.exe" />
+      </Tools>
+    </Environment>
+  </Environments>
+  <Project Name="Default" Env="Default">
+    <Task Name="Remote" Tool="Compile" WorkingDir="." />
+    <Task Name="Local" Tool="Compile" WorkingDir="." AllowRemote="False" StopOnErrors="False" SkipIfProjectFailed="true" AllowRestartOnLocal="false" />
+  </Project>
+</BuildSet>
+"#;
+
+#[test]
+fn test_parse_task_options() {
+    let mut graph = Graph::new();
+    parse(&mut graph, TASK_OPTIONS_XML.as_bytes()).unwrap();
+
+    assert_eq!(graph.node_count(), 2);
+    for node in graph.raw_nodes() {
+        let options = &node.weight.options;
+        assert_eq!(options.project, Some(0));
+        let remote = options.allow_remote;
+        assert_eq!(options.stop_on_errors, remote);
+        assert_eq!(options.skip_if_project_failed, !remote);
+        assert_eq!(options.allow_restart_on_local, remote);
+    }
+    assert_eq!(
+        graph
+            .raw_nodes()
+            .iter()
+            .filter(|n| n.weight.options.allow_remote)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_parse_multiple_files() {
+    let mut graph = Graph::new();
+    parse(&mut graph, TASK_OPTIONS_XML.as_bytes()).unwrap();
+    parse(&mut graph, TASK_OPTIONS_XML.as_bytes()).unwrap();
+
+    // Projects from different files are different projects.
+    let projects: Vec<Option<usize>> = graph
+        .raw_nodes()
+        .iter()
+        .map(|n| n.weight.options.project)
+        .collect();
+    assert_eq!(projects, vec![Some(0), Some(0), Some(1), Some(1)]);
+}
+
+#[test]
+fn test_parse_project_depends() {
+    let mut graph = Graph::new();
+    parse(
+        &mut graph,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<BuildSet FormatVersion="1">
+  <Environments>
+    <Environment Name="Default">
+      <Tools>
+        <Tool Name="Compile" Path="cl.exe" />
+      </Tools>
+    </Environment>
+    <Environment Name="Default">
+      <Tools>
+        <Tool Name="Link" Path="link.exe" />
+      </Tools>
+    </Environment>
+  </Environments>
+  <Project Name="Core" Env="Default">
+    <Task Name="A" Caption="Core A" Tool="Compile" WorkingDir="." />
+    <Task Name="B" Caption="Core B" Tool="Link" WorkingDir="." DependsOn="A" />
+  </Project>
+  <Project Name="Game" Env="Default" DependsOn="Core">
+    <Task Name="A" Caption="Game A" Tool="Compile" WorkingDir="." />
+    <Task Name="B" Caption="Game B" Tool="Link" WorkingDir="." DependsOn="A;Core::A" />
+  </Project>
+</BuildSet>
+"#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let find = |title: &str| {
+        graph
+            .node_indices()
+            .find(|i| graph[*i].title == title)
+            .unwrap()
+    };
+    let depends = |title: &str| {
+        let mut result: Vec<String> = graph
+            .neighbors(find(title))
+            .map(|i| graph[i].title.clone())
+            .collect();
+        result.sort();
+        result
+    };
+    assert_eq!(graph.node_count(), 4);
+    assert_eq!(depends("Core A"), Vec::<String>::new());
+    assert_eq!(depends("Core B"), vec!["Core A"]);
+    assert_eq!(depends("Game A"), vec!["Core B"]);
+    assert_eq!(depends("Game B"), vec!["Core A", "Game A"]);
+}
+
+#[test]
+fn test_parse_dependency_not_found() {
+    let result = parse(
+        &mut Graph::new(),
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<BuildSet FormatVersion="1">
+  <Environments>
+    <Environment Name="Default">
+      <Tools>
+        <Tool Name="Compile" Path="cl.exe" />
+      </Tools>
+    </Environment>
+  </Environments>
+  <Project Name="Game" Env="Default">
+    <Task Name="A" Tool="Compile" WorkingDir="." DependsOn="Core::A" />
+  </Project>
+</BuildSet>
+"#
+        .as_bytes(),
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "сan't find task for dependency with id: Core::A"
+    );
+}